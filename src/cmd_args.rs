@@ -15,6 +15,68 @@ pub struct CmdArgs {
         default_value = ""
     )]
     pub log_path: NoneableString,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum Command {
+    /// Forward debuggee connections between an encrypted and a plain side, so emmy_core
+    /// builds without tls can be debugged over a tls channel
+    Relay(RelayArgs),
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct RelayArgs {
+    /// Address the relay accepts connections on
+    #[arg(long, help = "Address to accept connections on, e.g. 0.0.0.0:9967")]
+    pub listen: String,
+
+    /// Address every accepted connection is forwarded to
+    #[arg(long, help = "Address to forward connections to, e.g. 127.0.0.1:9966")]
+    pub connect: String,
+
+    /// Which side of the relay speaks tls
+    #[arg(
+        long = "tls-side",
+        help = "Encrypted side: 'listen' when the adapter connects to the relay, 'connect' when the relay connects to a listening adapter",
+        default_value = "listen"
+    )]
+    pub tls_side: TlsSide,
+
+    #[arg(long, help = "Certificate (pem) the relay presents")]
+    pub cert: Option<String>,
+
+    #[arg(long, help = "Private key (pem) of the certificate")]
+    pub key: Option<String>,
+
+    #[arg(
+        long,
+        help = "Authority (pem) the peer's certificate is checked against"
+    )]
+    pub ca: Option<String>,
+
+    #[arg(
+        long = "insecure-skip-verify",
+        help = "Accept any certificate from the adapter"
+    )]
+    pub insecure_skip_verify: bool,
+
+    #[arg(
+        long,
+        help = "Shared secret to answer the challenge of an adapter listening with `auth.secret`"
+    )]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+#[value(rename_all = "lowercase")]
+pub enum TlsSide {
+    /// connections accepted by the relay are encrypted
+    Listen,
+    /// connections opened by the relay are encrypted
+    Connect,
 }
 
 /// Logging level enum
@@ -0,0 +1,55 @@
+/// How the editor counts positions, told in its initialize request.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientOptions {
+    pub lines_start_at1: bool,
+    pub columns_start_at1: bool,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            lines_start_at1: true,
+            columns_start_at1: true,
+        }
+    }
+}
+
+/// The adapter counts lines and columns from 1, these convert at the protocol boundary.
+impl ClientOptions {
+    pub fn client_line(self, line: i64) -> i64 {
+        line - 1 + self.lines_start_at1 as i64
+    }
+
+    pub fn adapter_line(self, line: i64) -> i64 {
+        line + 1 - self.lines_start_at1 as i64
+    }
+
+    pub fn client_column(self, column: i64) -> i64 {
+        column - 1 + self.columns_start_at1 as i64
+    }
+
+    pub fn adapter_column(self, column: i64) -> i64 {
+        column + 1 - self.columns_start_at1 as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_zero_based_positions() {
+        let client = ClientOptions {
+            lines_start_at1: false,
+            columns_start_at1: false,
+        };
+        assert_eq!(client.client_line(1), 0);
+        assert_eq!(client.adapter_line(0), 1);
+        assert_eq!(client.client_column(5), 4);
+        assert_eq!(client.adapter_column(4), 5);
+
+        let client = ClientOptions::default();
+        assert_eq!(client.client_line(3), 3);
+        assert_eq!(client.client_column(7), 7);
+    }
+}
@@ -0,0 +1,308 @@
+use std::net::IpAddr;
+
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{DebuggerError, DebuggerResult};
+
+/// Frame ids of the handshake, outside the range of emmy commands. The adapter sends
+/// `100` with a random challenge, the peer answers `101` with the hex hmac-sha256 of
+/// the challenge bytes keyed by the shared secret.
+const CHALLENGE_ID: &str = "100";
+const RESPONSE_ID: &str = "101";
+const MAX_LINE: usize = 1024;
+
+#[derive(Serialize, Deserialize)]
+struct Challenge {
+    challenge: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChallengeResponse {
+    response: String,
+}
+
+/// Who may connect to a listening adapter.
+#[derive(Debug, Clone, Default)]
+pub struct PeerGuard {
+    secret: Option<String>,
+    /// networks of allowed peers, empty allows every address
+    allowed: Vec<(IpAddr, u8)>,
+}
+
+impl PeerGuard {
+    /// `allowed_peers` holds addresses or networks, `10.0.0.0/8`, `::1`.
+    pub fn new(secret: Option<String>, allowed_peers: &[String]) -> DebuggerResult<Self> {
+        let allowed = allowed_peers
+            .iter()
+            .map(|peer| parse_network(peer))
+            .collect::<DebuggerResult<Vec<_>>>()?;
+        Ok(PeerGuard { secret, allowed })
+    }
+
+    /// Peers of local transports have no address, the file system guards those.
+    pub fn allows(&self, peer_ip: Option<IpAddr>) -> bool {
+        let Some(peer_ip) = peer_ip else {
+            return true;
+        };
+        let peer_ip = peer_ip.to_canonical();
+        self.allowed.is_empty()
+            || self
+                .allowed
+                .iter()
+                .any(|(network, prefix)| in_network(peer_ip, *network, *prefix))
+    }
+
+    pub fn secret(&self) -> Option<&str> {
+        self.secret.as_deref()
+    }
+}
+
+/// Challenge a freshly accepted peer, fails unless it proves to know the secret.
+pub async fn challenge<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    secret: &str,
+) -> DebuggerResult<()> {
+    let mut nonce = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| DebuggerError::ConnectionError("no randomness for the challenge".into()))?;
+    let challenge = Challenge {
+        challenge: to_hex(&nonce),
+    };
+    write_frame(stream, CHALLENGE_ID, &challenge).await?;
+
+    let (id, json) = read_frame(stream).await?;
+    let response = (id == RESPONSE_ID)
+        .then(|| serde_json::from_str::<ChallengeResponse>(&json).ok())
+        .flatten()
+        .and_then(|response| from_hex(&response.response))
+        .ok_or_else(|| DebuggerError::ConnectionError("no answer to the challenge".into()))?;
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::verify(&key, &nonce, &response)
+        .map_err(|_| DebuggerError::ConnectionError("wrong answer to the challenge".into()).into())
+}
+
+/// Answer the challenge of a listening adapter, the peer side of `challenge`.
+pub async fn answer<R, W>(reader: &mut R, writer: &mut W, secret: &str) -> DebuggerResult<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let (id, json) = read_frame(reader).await?;
+    let nonce = (id == CHALLENGE_ID)
+        .then(|| serde_json::from_str::<Challenge>(&json).ok())
+        .flatten()
+        .and_then(|challenge| from_hex(&challenge.challenge))
+        .ok_or_else(|| DebuggerError::ConnectionError("expected a challenge".into()))?;
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let response = ChallengeResponse {
+        response: to_hex(hmac::sign(&key, &nonce).as_ref()),
+    };
+    write_frame(writer, RESPONSE_ID, &response).await
+}
+
+async fn write_frame<W: AsyncWrite + Unpin, T: Serialize>(
+    writer: &mut W,
+    id: &str,
+    body: &T,
+) -> DebuggerResult<()> {
+    let json = serde_json::to_string(body)
+        .map_err(|e| DebuggerError::SerializationError(e.to_string()))?;
+    writer
+        .write_all(format!("{}\n{}\n", id, json).as_bytes())
+        .await
+        .map_err(DebuggerError::from)?;
+    writer.flush().await.map_err(DebuggerError::from)?;
+    Ok(())
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> DebuggerResult<(String, String)> {
+    let id = read_line(reader).await?;
+    let json = read_line(reader).await?;
+    Ok((id, json))
+}
+
+/// Byte by byte, whatever follows the handshake belongs to the reader task.
+async fn read_line<R: AsyncRead + Unpin>(reader: &mut R) -> DebuggerResult<String> {
+    let mut line = vec![];
+    loop {
+        let byte = reader.read_u8().await.map_err(DebuggerError::from)?;
+        if byte == b'\n' {
+            break;
+        }
+        if line.len() == MAX_LINE {
+            return Err(DebuggerError::ConnectionError("handshake line too long".into()).into());
+        }
+        line.push(byte);
+    }
+    Ok(String::from_utf8_lossy(&line).trim().to_string())
+}
+
+fn parse_network(peer: &str) -> DebuggerResult<(IpAddr, u8)> {
+    let invalid = || DebuggerError::ConnectionError(format!("invalid peer address {}", peer));
+    let (addr, prefix) = match peer.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (peer, None),
+    };
+    let parsed = addr.parse::<IpAddr>().map_err(|_| invalid())?;
+    let max_prefix = if parsed.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+        None => max_prefix,
+    };
+    if prefix > max_prefix {
+        return Err(invalid().into());
+    }
+    // peers are matched as v4 when mapped, a mapped network is taken as its v4 part
+    let addr = parsed.to_canonical();
+    if parsed.is_ipv6() && addr.is_ipv4() {
+        return match prefix.checked_sub(96) {
+            Some(prefix) => Ok((addr, prefix)),
+            None => Err(invalid().into()),
+        };
+    }
+    Ok((addr, prefix))
+}
+
+fn in_network(addr: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (addr, network) {
+        (IpAddr::V4(addr), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(addr) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(addr), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(addr) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn guard(allowed_peers: &[&str]) -> PeerGuard {
+        let allowed_peers = allowed_peers
+            .iter()
+            .map(|peer| peer.to_string())
+            .collect::<Vec<_>>();
+        PeerGuard::new(None, &allowed_peers).unwrap()
+    }
+
+    #[test]
+    fn parses_networks() {
+        assert_eq!(parse_network("10.0.0.0/8").unwrap(), (ip("10.0.0.0"), 8));
+        assert_eq!(parse_network("10.1.2.3").unwrap(), (ip("10.1.2.3"), 32));
+        assert_eq!(parse_network("::1").unwrap(), (ip("::1"), 128));
+        assert_eq!(parse_network("0.0.0.0/0").unwrap(), (ip("0.0.0.0"), 0));
+        // v4 mapped addresses are kept as the v4 address they stand for
+        assert_eq!(
+            parse_network("::ffff:10.0.0.1").unwrap(),
+            (ip("10.0.0.1"), 32)
+        );
+        assert_eq!(
+            parse_network("::ffff:10.0.0.0/104").unwrap(),
+            (ip("10.0.0.0"), 8)
+        );
+        assert!(parse_network("::ffff:10.0.0.0/64").is_err());
+        assert!(parse_network("10.0.0.0/33").is_err());
+        assert!(parse_network("::/129").is_err());
+        assert!(parse_network("10.0.0.0/").is_err());
+        assert!(parse_network("localhost").is_err());
+    }
+
+    #[test]
+    fn matches_networks() {
+        assert!(in_network(ip("10.9.8.7"), ip("10.0.0.0"), 8));
+        assert!(!in_network(ip("11.0.0.1"), ip("10.0.0.0"), 8));
+        assert!(in_network(ip("192.168.1.1"), ip("0.0.0.0"), 0));
+        assert!(in_network(ip("10.1.2.3"), ip("10.1.2.3"), 32));
+        assert!(!in_network(ip("10.1.2.4"), ip("10.1.2.3"), 32));
+        assert!(in_network(ip("fe80::1"), ip("::"), 0));
+        assert!(in_network(ip("::1"), ip("::1"), 128));
+        assert!(!in_network(ip("::2"), ip("::1"), 128));
+        assert!(!in_network(ip("10.0.0.1"), ip("::"), 0));
+    }
+
+    #[test]
+    fn allows_listed_peers() {
+        assert!(guard(&[]).allows(Some(ip("203.0.113.1"))));
+        let guard = guard(&["10.0.0.0/8", "::1"]);
+        assert!(guard.allows(Some(ip("10.1.1.1"))));
+        assert!(guard.allows(Some(ip("::ffff:10.1.1.1"))));
+        assert!(guard.allows(Some(ip("::1"))));
+        assert!(!guard.allows(Some(ip("127.0.0.1"))));
+        assert!(!guard.allows(Some(ip("::ffff:192.168.0.1"))));
+        // local transports have no address
+        assert!(guard.allows(None));
+    }
+
+    async fn handshake(adapter_secret: &str, peer_secret: &str) -> DebuggerResult<()> {
+        let (mut adapter, peer) = tokio::io::duplex(4096);
+        let (mut reader, mut writer) = tokio::io::split(peer);
+        let peer_secret = peer_secret.to_string();
+        let answering =
+            tokio::spawn(async move { answer(&mut reader, &mut writer, &peer_secret).await });
+        let result = challenge(&mut adapter, adapter_secret).await;
+        answering.await.unwrap()?;
+        result
+    }
+
+    #[tokio::test]
+    async fn admits_peers_knowing_the_secret() {
+        assert!(handshake("secret", "secret").await.is_ok());
+        assert!(handshake("secret", "wrong").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_answers() {
+        let (mut adapter, mut peer) = tokio::io::duplex(4096);
+        let challenging = tokio::spawn(async move { challenge(&mut adapter, "secret").await });
+        read_frame(&mut peer).await.unwrap();
+        let response = ChallengeResponse {
+            response: "zz".to_string(),
+        };
+        write_frame(&mut peer, RESPONSE_ID, &response)
+            .await
+            .unwrap();
+        assert!(challenging.await.unwrap().is_err());
+
+        let (mut adapter, mut peer) = tokio::io::duplex(4096);
+        let challenging = tokio::spawn(async move { challenge(&mut adapter, "secret").await });
+        read_frame(&mut peer).await.unwrap();
+        write_frame(
+            &mut peer,
+            CHALLENGE_ID,
+            &Challenge {
+                challenge: String::new(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(challenging.await.unwrap().is_err());
+    }
+}
@@ -1,20 +1,37 @@
 use std::{collections::HashMap, sync::Arc};
 
-use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
-use super::{DebuggerConnection, Stack, ValueType, Variable};
+use super::{
+    DebuggerRequests, Stack, ValueType, Variable,
+    format::{
+        DEFAULT_MAX_STRING_LENGTH, STRING_CHUNK_SIZE, format_string_value, is_truncated_string,
+        split_string_chunks,
+    },
+};
 
 #[derive(Debug)]
 pub struct DebuggerCache {
+    /// ids are handed out in `first_id..end_id`, wrapping around when used up
+    first_id: i64,
+    end_id: i64,
     cache_id: i64,
     caches: HashMap<i64, DebuggerCacheItem>,
+    pub max_string_length: usize,
 }
 
 impl DebuggerCache {
     pub fn new() -> Self {
+        Self::with_range(1, i32::MAX as i64 - 1)
+    }
+
+    pub fn with_range(first_id: i64, size: i64) -> Self {
         DebuggerCache {
-            cache_id: 1,
+            first_id,
+            end_id: first_id + size,
+            cache_id: first_id,
             caches: HashMap::new(),
+            max_string_length: DEFAULT_MAX_STRING_LENGTH,
         }
     }
 }
@@ -41,10 +58,20 @@ impl DebuggerCache {
     }
 
     pub fn allocate_cache_id(&mut self) -> i64 {
+        if self.cache_id >= self.end_id {
+            log::warn!("Variables references used up, reusing them from the start");
+            self.cache_id = self.first_id;
+        }
         let cache_id = self.cache_id;
         self.cache_id += 1;
         cache_id
     }
+
+    /// References only live until the debuggee runs again.
+    pub fn clear(&mut self) {
+        self.caches.clear();
+        self.cache_id = self.first_id;
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -109,7 +136,9 @@ impl DebuggerCacheItem {
         }
     }
 
-    pub fn to_dap_variable(&self) -> dap::types::Variable {
+    /// Convert to a dap variable, strings longer than `max_string_length` are truncated
+    /// and made expandable, `None` renders the full value.
+    pub fn to_dap_variable(&self, max_string_length: Option<usize>) -> dap::types::Variable {
         match self {
             DebuggerCacheItem::Stack(_) => {
                 unreachable!("Stack should not be converted to dap variable")
@@ -123,7 +152,12 @@ impl DebuggerCacheItem {
                 let mut value = var.value.clone();
                 match var.value_type {
                     ValueType::TSTRING => {
-                        value = format!("\"{}\"", value);
+                        if let Some(max_string_length) = max_string_length
+                            && is_truncated_string(&var.value, max_string_length)
+                        {
+                            ref_id = var_ref.id;
+                        }
+                        value = format_string_value(&var.value, max_string_length);
                     }
                     ValueType::TTABLE | ValueType::TUSERDATA | ValueType::GROUP => {
                         ref_id = var_ref.id;
@@ -149,10 +183,13 @@ impl DebuggerCacheItem {
         }
     }
 
+    /// Children of a scope or variable, tables are fetched from the debuggee. Without a
+    /// connection only the recorded children are used, as for stops replayed from history.
     pub async fn compute_children(
         &self,
         cache: &mut DebuggerCache,
-        debugger_conn: Arc<Mutex<DebuggerConnection>>,
+        requests: Option<DebuggerRequests>,
+        cancel: &CancellationToken,
     ) -> Vec<dap::types::Variable> {
         match self {
             DebuggerCacheItem::Stack(stack_ref) => {
@@ -171,7 +208,7 @@ impl DebuggerCacheItem {
                         },
                     );
                     let var_item = DebuggerCacheItem::Variable(Arc::new(var_ref));
-                    result_variables.push(var_item.to_dap_variable());
+                    result_variables.push(var_item.to_dap_variable(Some(cache.max_string_length)));
                     cache.add_cache(var_item);
                 }
 
@@ -181,15 +218,30 @@ impl DebuggerCacheItem {
                 vec![]
             }
             DebuggerCacheItem::Variable(var_ref) => {
+                if var_ref.item.var.value_type == ValueType::TSTRING {
+                    let chunk_size = cache.max_string_length.max(STRING_CHUNK_SIZE);
+                    return split_string_chunks(&var_ref.item.var.value, chunk_size)
+                        .into_iter()
+                        .map(|(offset, chunk)| dap::types::Variable {
+                            name: format!("[{}..{}]", offset, offset + chunk.len()),
+                            value: format_string_value(chunk, None),
+                            variables_reference: 0,
+                            ..Default::default()
+                        })
+                        .collect();
+                }
+
                 let mut children = var_ref.item.var.children.clone();
-                if var_ref.item.var.value_type != ValueType::GROUP {
-                    let mut debugger_conn = debugger_conn.lock().await;
-                    let eval_rsp_result = debugger_conn
+                if var_ref.item.var.value_type != ValueType::GROUP
+                    && let Some(requests) = requests
+                {
+                    let eval_rsp_result = requests
                         .eval_expr(
                             var_ref.item.get_expr(cache),
                             var_ref.item.var.cache_id as i64,
                             2,
                             -1,
+                            cancel,
                         )
                         .await;
 
@@ -219,7 +271,8 @@ impl DebuggerCacheItem {
                             },
                         );
                         let child_item = DebuggerCacheItem::Variable(Arc::new(child_ref));
-                        result_variables.push(child_item.to_dap_variable());
+                        result_variables
+                            .push(child_item.to_dap_variable(Some(cache.max_string_length)));
                         cache.add_cache(child_item);
                     }
                     result_variables
@@ -230,3 +283,19 @@ impl DebuggerCacheItem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_and_clears_ids() {
+        let mut cache = DebuggerCache::with_range(11, 3);
+        let ids = (0..4)
+            .map(|_| cache.allocate_cache_id())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![11, 12, 13, 11]);
+        cache.clear();
+        assert_eq!(cache.allocate_cache_id(), 11);
+    }
+}
@@ -0,0 +1,12 @@
+use glob::Pattern;
+
+/// Match a chunk name against path globs, the `@` prefix is dropped and separators
+/// are normalized to `/`.
+pub fn chunk_matches(chunkname: &str, patterns: &[Pattern]) -> bool {
+    if patterns.is_empty() {
+        return false;
+    }
+    let chunkname = chunkname.strip_prefix('@').unwrap_or(chunkname);
+    let chunkname = chunkname.replace('\\', "/");
+    patterns.iter().any(|pattern| pattern.matches(&chunkname))
+}
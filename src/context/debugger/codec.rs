@@ -0,0 +1,442 @@
+use std::fmt;
+
+use serde_json::Value;
+use tokio_util::{
+    bytes::{Buf, BufMut, BytesMut},
+    codec::{Decoder, Encoder},
+};
+
+use super::{DebuggerError, Message, MessageCMD};
+
+/// messages from the debuggee larger than this are dropped unless configured otherwise
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// lines a json body may be split into, a body still open after that is dropped
+const MAX_BODY_LINES: usize = 256;
+
+/// What the reader saw on a link, logged on every resync and when the link drops.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameStats {
+    pub messages: u64,
+    pub bytes: u64,
+    /// times the link got out of step and lines were skipped to find the next message
+    pub resyncs: u64,
+    /// messages dropped for exceeding the size limit
+    pub oversized: u64,
+    /// messages dropped because their json could not be read
+    pub malformed: u64,
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} messages, {} bytes, {} resyncs, {} oversized, {} malformed",
+            self.messages, self.bytes, self.resyncs, self.oversized, self.malformed
+        )
+    }
+}
+
+enum DecodeState {
+    /// expecting a command id line
+    Header,
+    /// expecting the json of `cmd`, `pending` holds the `lines` before a raw newline in
+    /// it. `next` is a command id read as a continuation, it starts the next message when
+    /// a whole json object follows.
+    Body {
+        cmd: i64,
+        pending: String,
+        lines: usize,
+        next: Option<(i64, String)>,
+    },
+    /// dropping the rest of a message over the size limit
+    Discard,
+}
+
+/// Framing of the emmy protocol, `"<cmd>\n<json>\n"`. A line that does not fit where it
+/// is read puts the decoder out of step, it then skips lines up to the next command id.
+pub struct EmmyCodec {
+    max_message_size: usize,
+    state: DecodeState,
+    /// bytes of the buffer already searched for a newline
+    scanned: usize,
+    /// lines skipped since the link got out of step
+    skipped: usize,
+    stats: FrameStats,
+}
+
+impl EmmyCodec {
+    pub fn new(max_message_size: usize) -> Self {
+        EmmyCodec {
+            max_message_size,
+            state: DecodeState::Header,
+            scanned: 0,
+            skipped: 0,
+            stats: FrameStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// Next complete line without its line ending, `None` until one arrived.
+    fn next_line(&mut self, src: &mut BytesMut) -> Option<String> {
+        let pending = match &self.state {
+            DecodeState::Body { pending, .. } => pending.len(),
+            _ => 0,
+        };
+        match src[self.scanned..].iter().position(|b| *b == b'\n') {
+            Some(offset) => {
+                let line = src.split_to(self.scanned + offset + 1);
+                self.scanned = 0;
+                self.stats.bytes += line.len() as u64;
+                if matches!(self.state, DecodeState::Discard) {
+                    self.state = DecodeState::Header;
+                    return Some(String::new());
+                }
+                if line.len() + pending > self.max_message_size {
+                    self.drop_oversized();
+                    self.state = DecodeState::Header;
+                    return Some(String::new());
+                }
+                let line = String::from_utf8_lossy(&line);
+                Some(line.trim_end_matches(['\n', '\r']).to_string())
+            }
+            None if matches!(self.state, DecodeState::Discard) => {
+                self.stats.bytes += src.len() as u64;
+                src.advance(src.len());
+                self.scanned = 0;
+                None
+            }
+            None if src.len() + pending > self.max_message_size => {
+                self.drop_oversized();
+                self.stats.bytes += src.len() as u64;
+                src.advance(src.len());
+                self.scanned = 0;
+                self.state = DecodeState::Discard;
+                None
+            }
+            None => {
+                self.scanned = src.len();
+                None
+            }
+        }
+    }
+
+    fn drop_oversized(&mut self) {
+        self.stats.oversized += 1;
+        log::warn!(
+            "Dropping a message over {} bytes from the debuggee, {}",
+            self.max_message_size,
+            self.stats
+        );
+    }
+
+    /// A line that can not be where it was read, skip ahead to the next command id.
+    fn skip(&mut self, line: &str) {
+        if self.skipped == 0 {
+            self.stats.resyncs += 1;
+            log::warn!(
+                "Debug channel out of step at {:?}, skipping to the next message, {}",
+                preview(line),
+                self.stats
+            );
+        }
+        self.skipped += 1;
+    }
+
+    fn decode_line(&mut self, line: String) -> Option<Message> {
+        match std::mem::replace(&mut self.state, DecodeState::Header) {
+            DecodeState::Header | DecodeState::Discard => {
+                if let Some(cmd) = command_id(&line) {
+                    self.state = DecodeState::Body {
+                        cmd,
+                        pending: String::new(),
+                        lines: 0,
+                        next: None,
+                    };
+                } else if !line.trim().is_empty() {
+                    self.skip(&line);
+                }
+                None
+            }
+            DecodeState::Body {
+                cmd,
+                pending,
+                lines,
+                next,
+            } => {
+                let (pending, lines) = match next {
+                    Some((next_cmd, id_line)) => {
+                        // the unfinished body was cut off by a whole message
+                        if let Ok(value @ Value::Object(_)) = serde_json::from_str::<Value>(&line) {
+                            self.drop_unfinished(cmd, &pending);
+                            return self.finish(next_cmd, value);
+                        }
+                        (join_lines(&pending, &id_line), lines + 1)
+                    }
+                    None if !pending.is_empty() => match command_id(&line) {
+                        Some(next_cmd) => {
+                            self.state = DecodeState::Body {
+                                cmd,
+                                pending,
+                                lines,
+                                next: Some((next_cmd, line)),
+                            };
+                            return None;
+                        }
+                        None => (pending, lines),
+                    },
+                    None => (pending, lines),
+                };
+                let text = if pending.is_empty() {
+                    line
+                } else {
+                    join_lines(&pending, &line)
+                };
+                match serde_json::from_str::<Value>(&text) {
+                    Ok(value @ Value::Object(_)) => self.finish(cmd, value),
+                    Err(e) if e.is_eof() && lines + 1 < MAX_BODY_LINES => {
+                        self.state = DecodeState::Body {
+                            cmd,
+                            pending: text,
+                            lines: lines + 1,
+                            next: None,
+                        };
+                        None
+                    }
+                    Err(e) if e.is_eof() => {
+                        self.drop_unfinished(cmd, &text);
+                        None
+                    }
+                    // the json of `cmd` was lost, the line is the next message's id
+                    _ if pending.is_empty() && command_id(&text).is_some() => {
+                        self.skip(&format!("{} without a body", cmd));
+                        self.decode_line(text)
+                    }
+                    _ => {
+                        self.stats.malformed += 1;
+                        log::error!(
+                            "Error parsing message JSON of {}: {:?}",
+                            cmd,
+                            preview(&text)
+                        );
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    fn drop_unfinished(&mut self, cmd: i64, pending: &str) {
+        self.stats.malformed += 1;
+        log::error!(
+            "Dropping unfinished message JSON of {}: {:?}",
+            cmd,
+            preview(pending)
+        );
+    }
+
+    fn finish(&mut self, cmd: i64, value: Value) -> Option<Message> {
+        if self.skipped > 0 {
+            log::info!(
+                "Debug channel back in step after skipping {} lines",
+                self.skipped
+            );
+            self.skipped = 0;
+        }
+        match Message::from_value(value, MessageCMD::from(cmd)) {
+            Ok(message) => {
+                self.stats.messages += 1;
+                Some(message)
+            }
+            Err(e) => {
+                self.stats.malformed += 1;
+                log::error!("Error parsing message {}: {}", cmd, e);
+                None
+            }
+        }
+    }
+}
+
+impl Decoder for EmmyCodec {
+    type Item = Message;
+    type Error = DebuggerError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, DebuggerError> {
+        while let Some(line) = self.next_line(src) {
+            if let Some(message) = self.decode_line(line) {
+                return Ok(Some(message));
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Message>, DebuggerError> {
+        let message = self.decode(src)?;
+        if message.is_none() && !src.is_empty() {
+            log::warn!("Link closed inside a message, dropping {} bytes", src.len());
+            src.clear();
+        }
+        Ok(message)
+    }
+}
+
+impl Encoder<Message> for EmmyCodec {
+    type Error = DebuggerError;
+
+    fn encode(&mut self, message: Message, dst: &mut BytesMut) -> Result<(), DebuggerError> {
+        let json = serde_json::to_string(&message)
+            .map_err(|e| DebuggerError::SerializationError(format!("serde fail: {}", e)))?;
+        let header = format!("{}\n", message.get_cmd() as i32);
+        log::debug!("send message: {}{}", header, json);
+        dst.reserve(header.len() + json.len() + 1);
+        dst.put_slice(header.as_bytes());
+        dst.put_slice(json.as_bytes());
+        dst.put_u8(b'\n');
+        Ok(())
+    }
+}
+
+/// Join a line to the unfinished json before it. A raw newline inside a string stands
+/// for an escaped one, between tokens it is whitespace.
+fn join_lines(pending: &str, line: &str) -> String {
+    if in_string(pending) {
+        format!("{}\\n{}", pending, line)
+    } else {
+        format!("{}\n{}", pending, line)
+    }
+}
+
+/// Whether the json text ends inside a string literal.
+fn in_string(json: &str) -> bool {
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in json.chars() {
+        if escaped {
+            escaped = false;
+        } else if in_string && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_string = !in_string;
+        }
+    }
+    in_string
+}
+
+/// The id of a known command, when the line is one.
+fn command_id(line: &str) -> Option<i64> {
+    let cmd = line.trim().parse::<i64>().ok()?;
+    (MessageCMD::from(cmd) != MessageCMD::Unknown).then_some(cmd)
+}
+
+fn preview(line: &str) -> String {
+    line.chars().take(80).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(codec: &mut EmmyCodec, src: &mut BytesMut) -> Vec<String> {
+        let mut versions = vec![];
+        while let Some(message) = codec.decode(src).unwrap() {
+            match message {
+                Message::InitRsp(rsp) => versions.push(rsp.version),
+                other => panic!("unexpected message {:?}", other.get_cmd()),
+            }
+        }
+        versions
+    }
+
+    fn decode_str(codec: &mut EmmyCodec, text: &str) -> Vec<String> {
+        decode_all(codec, &mut BytesMut::from(text))
+    }
+
+    #[test]
+    fn decodes_messages_split_across_reads() {
+        let mut codec = EmmyCodec::new(DEFAULT_MAX_MESSAGE_SIZE);
+        let mut src = BytesMut::new();
+        let mut versions = vec![];
+        for chunk in [
+            "2",
+            "\n{\"vers",
+            "ion\":\"1\"}\r",
+            "\n2\n{\"version\":\"2\"}\n",
+        ] {
+            src.extend_from_slice(chunk.as_bytes());
+            versions.extend(decode_all(&mut codec, &mut src));
+        }
+        assert_eq!(versions, ["1", "2"]);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn joins_raw_newlines() {
+        let mut codec = EmmyCodec::new(DEFAULT_MAX_MESSAGE_SIZE);
+        // inside a string the newline belongs to the value, between tokens it is spacing
+        let versions = decode_str(
+            &mut codec,
+            "2\n{\"version\":\"a\nb\"}\n2\n{\n\"version\":\n\"c\"\n}\n",
+        );
+        assert_eq!(versions, ["a\nb", "c"]);
+        assert_eq!(codec.stats().malformed, 0);
+    }
+
+    #[test]
+    fn drops_oversized_messages() {
+        let mut codec = EmmyCodec::new(32);
+        let big = format!("2\n{{\"version\":\"{}\"}}\n", "x".repeat(64));
+        let mut src = BytesMut::from(&big[..40]);
+        assert!(decode_all(&mut codec, &mut src).is_empty());
+        src.extend_from_slice(&big.as_bytes()[40..]);
+        src.extend_from_slice(b"2\n{\"version\":\"1\"}\n");
+        assert_eq!(decode_all(&mut codec, &mut src), ["1"]);
+        assert_eq!(codec.stats().oversized, 1);
+    }
+
+    #[test]
+    fn resyncs_on_the_next_command() {
+        let mut codec = EmmyCodec::new(DEFAULT_MAX_MESSAGE_SIZE);
+        let versions = decode_str(&mut codec, "garbage\n}\n2\n2\n{\"version\":\"1\"}\n");
+        assert_eq!(versions, ["1"]);
+        assert_eq!(codec.stats().resyncs, 1);
+    }
+
+    #[test]
+    fn a_whole_message_ends_an_unfinished_body() {
+        let mut codec = EmmyCodec::new(DEFAULT_MAX_MESSAGE_SIZE);
+        let versions = decode_str(
+            &mut codec,
+            "2\n{\"version\":\"cut\n2\n{\"version\":\"1\"}\n",
+        );
+        assert_eq!(versions, ["1"]);
+        assert_eq!(codec.stats().malformed, 1);
+
+        // a number on its own line inside a string is kept
+        let versions = decode_str(&mut codec, "2\n{\"version\":\"a\n2\nb\"}\n");
+        assert_eq!(versions, ["a\n2\nb"]);
+    }
+
+    #[test]
+    fn caps_the_lines_of_a_body() {
+        let mut codec = EmmyCodec::new(DEFAULT_MAX_MESSAGE_SIZE);
+        let open = format!("2\n{{\"version\":\"{}", "x\n".repeat(MAX_BODY_LINES));
+        assert!(decode_str(&mut codec, &open).is_empty());
+        assert_eq!(codec.stats().malformed, 1);
+        assert_eq!(decode_str(&mut codec, "2\n{\"version\":\"1\"}\n"), ["1"]);
+    }
+
+    #[test]
+    fn drops_a_message_cut_by_the_end_of_the_link() {
+        let mut codec = EmmyCodec::new(DEFAULT_MAX_MESSAGE_SIZE);
+        let mut src = BytesMut::from("2\n{\"version\":\"1\"}\n2\n{\"vers");
+        assert!(matches!(
+            codec.decode_eof(&mut src),
+            Ok(Some(Message::InitRsp(_)))
+        ));
+        assert!(codec.decode_eof(&mut src).unwrap().is_none());
+        assert!(src.is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::oneshot;
+
+use super::{EvalRsp, Message, MessageCMD};
+
+/// Round trips waiting for the debuggee. Evals carry a seq, the other responses carry
+/// no id but are answered in order, so they are matched first in first out per command.
+#[derive(Debug, Default)]
+pub struct RequestCorrelator {
+    next_id: u64,
+    evals: HashMap<i64, oneshot::Sender<EvalRsp>>,
+    responses: HashMap<MessageCMD, VecDeque<Waiter>>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    sender: oneshot::Sender<Message>,
+    /// set once the request gave up, a late answer is only expected until then
+    abandoned_until: Option<Instant>,
+}
+
+impl RequestCorrelator {
+    pub fn expect_eval(&mut self, seq: i64) -> oneshot::Receiver<EvalRsp> {
+        let (sender, receiver) = oneshot::channel();
+        self.evals.insert(seq, sender);
+        receiver
+    }
+
+    /// A late answer to a forgotten eval is dropped.
+    pub fn forget_eval(&mut self, seq: i64) {
+        self.evals.remove(&seq);
+    }
+
+    /// Returns the id to forget the request with if it never reached the debuggee.
+    pub fn expect_response(&mut self, cmd: MessageCMD) -> (u64, oneshot::Receiver<Message>) {
+        let (sender, receiver) = oneshot::channel();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.responses.entry(cmd).or_default().push_back(Waiter {
+            id,
+            sender,
+            abandoned_until: None,
+        });
+        (id, receiver)
+    }
+
+    /// Only for requests that were never sent.
+    pub fn forget_response(&mut self, cmd: MessageCMD, id: u64) {
+        if let Some(waiters) = self.responses.get_mut(&cmd) {
+            waiters.retain(|waiter| waiter.id != id);
+        }
+    }
+
+    /// A request that timed out keeps its slot for `late_by`, so a late answer is not
+    /// taken for the answer of the next one. Responses arriving after that skip it.
+    pub fn abandon_response(&mut self, cmd: MessageCMD, id: u64, late_by: Duration) {
+        let waiter = self
+            .responses
+            .get_mut(&cmd)
+            .and_then(|waiters| waiters.iter_mut().find(|waiter| waiter.id == id));
+        if let Some(waiter) = waiter {
+            waiter.abandoned_until = Some(Instant::now() + late_by);
+        }
+    }
+
+    /// Hand a response to its waiter, messages nobody waits for are given back.
+    pub fn resolve(&mut self, message: Message) -> Option<Message> {
+        if let Message::EvalRsp(eval_rsp) = message {
+            let seq = eval_rsp.seq as i64;
+            if let Some(sender) = self.evals.remove(&seq) {
+                let _ = sender.send(eval_rsp);
+            } else {
+                log::warn!("Dropping eval response {} nobody waits for", seq);
+            }
+            return None;
+        }
+
+        let Some(waiters) = self.responses.get_mut(&message.get_cmd()) else {
+            return Some(message);
+        };
+        let now = Instant::now();
+        while let Some(waiter) = waiters.pop_front() {
+            if waiter.abandoned_until.is_some_and(|until| until <= now) {
+                log::warn!(
+                    "Evicting {:?} request that was never answered",
+                    message.get_cmd()
+                );
+                continue;
+            }
+            let _ = waiter.sender.send(message);
+            return None;
+        }
+        Some(message)
+    }
+
+    /// The link is gone, every waiter gets a closed channel.
+    pub fn fail_all(&mut self) {
+        self.evals.clear();
+        self.responses.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::InitRsp;
+
+    fn init_rsp(version: &str) -> Message {
+        Message::InitRsp(InitRsp {
+            version: version.to_string(),
+        })
+    }
+
+    fn version(receiver: &mut oneshot::Receiver<Message>) -> Option<String> {
+        match receiver.try_recv() {
+            Ok(Message::InitRsp(rsp)) => Some(rsp.version),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn answers_in_order() {
+        let mut correlator = RequestCorrelator::default();
+        let (_, mut first) = correlator.expect_response(MessageCMD::InitRsp);
+        let (_, mut second) = correlator.expect_response(MessageCMD::InitRsp);
+        assert!(correlator.resolve(init_rsp("1")).is_none());
+        assert!(correlator.resolve(init_rsp("2")).is_none());
+        assert_eq!(version(&mut first).as_deref(), Some("1"));
+        assert_eq!(version(&mut second).as_deref(), Some("2"));
+        assert!(correlator.resolve(init_rsp("3")).is_some());
+    }
+
+    #[test]
+    fn late_answers_go_to_the_abandoned_request() {
+        let mut correlator = RequestCorrelator::default();
+        let (id, first) = correlator.expect_response(MessageCMD::InitRsp);
+        drop(first);
+        correlator.abandon_response(MessageCMD::InitRsp, id, Duration::from_secs(60));
+        let (_, mut second) = correlator.expect_response(MessageCMD::InitRsp);
+        assert!(correlator.resolve(init_rsp("late")).is_none());
+        assert_eq!(version(&mut second), None);
+        assert!(correlator.resolve(init_rsp("2")).is_none());
+        assert_eq!(version(&mut second).as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn evicts_requests_never_answered() {
+        let mut correlator = RequestCorrelator::default();
+        let (id, first) = correlator.expect_response(MessageCMD::InitRsp);
+        drop(first);
+        correlator.abandon_response(MessageCMD::InitRsp, id, Duration::ZERO);
+        let (_, mut second) = correlator.expect_response(MessageCMD::InitRsp);
+        assert!(correlator.resolve(init_rsp("2")).is_none());
+        assert_eq!(version(&mut second).as_deref(), Some("2"));
+    }
+}
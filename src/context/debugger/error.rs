@@ -5,7 +5,11 @@ pub enum DebuggerError {
     IoError(std::io::Error),
     AddrParseError(std::net::AddrParseError),
     ConnectionError(String),
+    TlsError(String),
     SerializationError(String),
+    /// the debuggee did not answer a request in time
+    Timeout(String),
+    Cancelled,
 }
 
 impl std::fmt::Display for DebuggerError {
@@ -14,7 +18,10 @@ impl std::fmt::Display for DebuggerError {
             DebuggerError::IoError(err) => write!(f, "IO Error: {}", err),
             DebuggerError::AddrParseError(err) => write!(f, "Parse Error: {}", err),
             DebuggerError::ConnectionError(msg) => write!(f, "Connection Error: {}", msg),
+            DebuggerError::TlsError(msg) => write!(f, "TLS Error: {}", msg),
             DebuggerError::SerializationError(msg) => write!(f, "Serialization Error: {}", msg),
+            DebuggerError::Timeout(msg) => write!(f, "Timeout: {}", msg),
+            DebuggerError::Cancelled => write!(f, "Request cancelled"),
         }
     }
 }
@@ -0,0 +1,170 @@
+use std::fmt::Write;
+
+use crate::lua_syntax::LUA_KEYWORDS;
+
+use super::{ValueType, Variable};
+
+pub const DEFAULT_MAX_STRING_LENGTH: usize = 256;
+
+/// bytes per child of an expanded long string, a string of megabytes stays a short list
+pub const STRING_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Escape a raw string the way a Lua string literal would be written.
+pub fn escape_lua_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            '\x07' => result.push_str("\\a"),
+            '\x08' => result.push_str("\\b"),
+            '\x0B' => result.push_str("\\v"),
+            '\x0C' => result.push_str("\\f"),
+            c if c.is_ascii_control() => {
+                let _ = write!(result, "\\{:03}", c as u32);
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Render a string value as a quoted Lua literal, truncated to `max_length` bytes
+/// when a limit is given.
+pub fn format_string_value(value: &str, max_length: Option<usize>) -> String {
+    match max_length {
+        Some(max_length) if value.len() > max_length => {
+            let end = floor_char_boundary(value, max_length);
+            format!(
+                "\"{}\"...({} bytes)",
+                escape_lua_string(&value[..end]),
+                value.len()
+            )
+        }
+        _ => format!("\"{}\"", escape_lua_string(value)),
+    }
+}
+
+pub fn is_truncated_string(value: &str, max_length: usize) -> bool {
+    value.len() > max_length
+}
+
+/// Split a string into chunks of at most `chunk_size` bytes, never cutting a utf8 char.
+/// Returns the byte offset of each chunk together with the chunk.
+pub fn split_string_chunks(value: &str, chunk_size: usize) -> Vec<(usize, &str)> {
+    let chunk_size = chunk_size.max(4);
+    let mut chunks = vec![];
+    let mut start = 0;
+    while start < value.len() {
+        let end = floor_char_boundary(value, start + chunk_size);
+        chunks.push((start, &value[start..end]));
+        start = end;
+    }
+    chunks
+}
+
+fn floor_char_boundary(value: &str, index: usize) -> usize {
+    if index >= value.len() {
+        return value.len();
+    }
+
+    let mut index = index;
+    while !value.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Serialize a variable and its fetched children as lua source, used when copying values.
+pub fn serialize_variable(var: &Variable) -> String {
+    let mut out = String::new();
+    write_variable(var, 0, &mut out);
+    out
+}
+
+fn write_variable(var: &Variable, indent: usize, out: &mut String) {
+    match var.value_type {
+        ValueType::TSTRING => out.push_str(&format_string_value(&var.value, None)),
+        ValueType::TTABLE | ValueType::GROUP => match &var.children {
+            Some(children) if !children.is_empty() => {
+                out.push_str("{\n");
+                for child in children {
+                    out.push_str(&"    ".repeat(indent + 1));
+                    write_key(child, out);
+                    out.push_str(" = ");
+                    write_variable(child, indent + 1, out);
+                    out.push_str(",\n");
+                }
+                out.push_str(&"    ".repeat(indent));
+                out.push('}');
+            }
+            _ => out.push_str(&var.value),
+        },
+        _ => out.push_str(&var.value),
+    }
+}
+
+fn write_key(var: &Variable, out: &mut String) {
+    if var.name_type != ValueType::TSTRING {
+        let _ = write!(out, "[{}]", var.name);
+    } else if is_identifier(&var.name) {
+        out.push_str(&var.name);
+    } else {
+        let _ = write!(out, "[{}]", format_string_value(&var.name, None));
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_ok = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    starts_ok
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !LUA_KEYWORDS.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_like_a_lua_literal() {
+        assert_eq!(escape_lua_string("plain"), "plain");
+        assert_eq!(escape_lua_string("a\"b\\c"), "a\\\"b\\\\c");
+        assert_eq!(escape_lua_string("1\n2\t3\r"), "1\\n2\\t3\\r");
+        assert_eq!(escape_lua_string("\x07\x08\x0B\x0C"), "\\a\\b\\v\\f");
+        // padded, so a digit after the escape is not read as part of it
+        assert_eq!(escape_lua_string("\x011"), "\\0011");
+        assert_eq!(escape_lua_string("中文"), "中文");
+    }
+
+    #[test]
+    fn truncates_on_char_boundaries() {
+        assert_eq!(format_string_value("abc", Some(3)), "\"abc\"");
+        assert_eq!(format_string_value("abcd", Some(3)), "\"abc\"...(4 bytes)");
+        // the limit falls inside the second 3 byte char
+        assert_eq!(format_string_value("中文", Some(4)), "\"中\"...(6 bytes)");
+        assert_eq!(format_string_value("a\nb", None), "\"a\\nb\"");
+    }
+
+    #[test]
+    fn splits_into_chunks() {
+        assert!(split_string_chunks("", 8).is_empty());
+        assert_eq!(
+            split_string_chunks("abcdefghij", 4),
+            vec![(0, "abcd"), (4, "efgh"), (8, "ij")]
+        );
+        // sizes under 4 would not fit a utf8 char
+        assert_eq!(split_string_chunks("abcde", 1), vec![(0, "abcd"), (4, "e")]);
+        let chunks = split_string_chunks("a中文b", 4);
+        assert_eq!(chunks, vec![(0, "a中"), (4, "文b")]);
+        assert_eq!(
+            chunks.iter().map(|(_, chunk)| *chunk).collect::<String>(),
+            "a中文b"
+        );
+    }
+}
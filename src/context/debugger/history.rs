@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+
+use super::Stack;
+
+/// One recorded stop, the tables among the locals of its top frame carry their children.
+#[derive(Debug, Clone)]
+pub struct StopSnapshot {
+    pub stacks: Vec<Stack>,
+    pub stopped_thread_id: i64,
+    /// `stop_seq` of the live stop, tables fetched later go to the right snapshot
+    pub stop_seq: u64,
+    /// the stop ended a step, the others hit a breakpoint or a pause
+    pub by_step: bool,
+}
+
+/// Ring buffer of the last stops, empty unless `history` is set in the launch
+/// configuration. The newest snapshot is always the live stop.
+#[derive(Debug, Default)]
+pub struct StopHistory {
+    /// how many stops are kept, 0 turns recording off
+    pub size: usize,
+    /// depth of the table children fetched for every local
+    pub depth: i64,
+    snapshots: VecDeque<StopSnapshot>,
+    /// index of the snapshot being replayed, none while at the live stop
+    replay_index: Option<usize>,
+}
+
+impl StopHistory {
+    pub fn new(size: usize, depth: i64) -> Self {
+        StopHistory {
+            size,
+            depth,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.size > 0
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay_index.is_some()
+    }
+
+    pub fn stop_count(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn replay_index(&self) -> Option<usize> {
+        self.replay_index
+    }
+
+    pub fn push(&mut self, snapshot: StopSnapshot) {
+        if !self.is_enabled() {
+            return;
+        }
+        while self.snapshots.len() >= self.size {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+        self.replay_index = None;
+    }
+
+    /// The snapshot of the live stop `stop_seq`, while it is the newest.
+    pub fn live_mut(&mut self, stop_seq: u64) -> Option<&mut StopSnapshot> {
+        self.snapshots
+            .back_mut()
+            .filter(|snapshot| snapshot.stop_seq == stop_seq)
+    }
+
+    /// Move to the previous snapshot, `to_breakpoint` skips the ones that ended a step.
+    pub fn back(&mut self, to_breakpoint: bool) -> Option<&StopSnapshot> {
+        let current = self
+            .replay_index
+            .unwrap_or(self.snapshots.len().checked_sub(1)?);
+        if current == 0 {
+            return None;
+        }
+        let index = if to_breakpoint {
+            (0..current)
+                .rev()
+                .find(|index| !self.snapshots[*index].by_step)
+                .unwrap_or(0)
+        } else {
+            current - 1
+        };
+        self.replay_index = Some(index);
+        self.snapshots.get(index)
+    }
+
+    /// Move to the next snapshot, or straight to the live stop. Leaves replay once the
+    /// live stop is reached.
+    pub fn forward(&mut self, to_live: bool) -> Option<&StopSnapshot> {
+        let current = self.replay_index?;
+        let index = if to_live {
+            self.snapshots.len() - 1
+        } else {
+            current + 1
+        };
+        self.replay_index = if index + 1 < self.snapshots.len() {
+            Some(index)
+        } else {
+            None
+        };
+        self.snapshots.get(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(stop_seq: u64, by_step: bool) -> StopSnapshot {
+        StopSnapshot {
+            stacks: vec![],
+            stopped_thread_id: 1,
+            stop_seq,
+            by_step,
+        }
+    }
+
+    #[test]
+    fn replays_back_and_forward() {
+        let mut history = StopHistory::new(3, 2);
+        for stop_seq in 1..=4 {
+            history.push(snapshot(stop_seq, stop_seq != 2));
+        }
+        assert_eq!(history.stop_count(), 3);
+        assert_eq!(history.back(false).map(|s| s.stop_seq), Some(3));
+        assert_eq!(history.back(true).map(|s| s.stop_seq), Some(2));
+        assert!(history.back(false).is_none());
+        assert!(history.live_mut(4).is_some());
+        assert_eq!(history.forward(true).map(|s| s.stop_seq), Some(4));
+        assert!(!history.is_replaying());
+    }
+
+    #[test]
+    fn fills_only_the_live_stop() {
+        let mut history = StopHistory::new(2, 2);
+        history.push(snapshot(1, false));
+        assert!(history.live_mut(1).is_some());
+        history.push(snapshot(2, true));
+        assert!(history.live_mut(1).is_none());
+        assert!(StopHistory::default().live_mut(0).is_none());
+    }
+}
@@ -1,37 +1,87 @@
+mod auth;
 mod cache;
+mod chunk;
+mod codec;
+mod correlator;
 mod error;
+mod format;
+mod history;
 mod proto;
-
-use cache::DebuggerCache;
+mod requests;
+mod step;
+mod thread;
+mod tls;
+mod transport;
+mod watch;
+
+pub use auth::{PeerGuard, answer as answer_challenge};
 pub use cache::*;
+pub use chunk::chunk_matches;
+pub use codec::DEFAULT_MAX_MESSAGE_SIZE;
+use codec::EmmyCodec;
+use correlator::RequestCorrelator;
 use dap::events::{Event, OutputEventBody};
 use dap::server::ServerOutput;
 pub use error::DebuggerError;
+pub use format::{DEFAULT_MAX_STRING_LENGTH, format_string_value, serialize_variable};
+use futures_util::StreamExt;
+pub use history::{StopHistory, StopSnapshot};
 #[allow(unused)]
 pub use proto::*;
+pub use requests::DebuggerRequests;
 use std::collections::HashMap;
 use std::error::Error;
 use std::io::Stdout;
-use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
-use tokio::net::{TcpListener, TcpStream};
+pub use step::StepInGoal;
+pub use thread::*;
+pub use tls::{tls_acceptor, tls_connector};
 use tokio::sync::{Mutex, mpsc};
 use tokio::task::JoinHandle;
-use tokio::time::timeout;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio_util::codec::{FramedRead, FramedWrite};
+pub use transport::{
+    DebuggerEndpoint, DebuggerListener, LinkReader, LinkWriter, open as open_link,
+};
+pub use watch::WatchValue;
 
 type DebuggerResult<T> = Result<T, Box<dyn Error + Send>>;
 
-#[derive(Debug)]
+/// requests the debuggee does not answer within this time fail with a timeout error
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// a single attempt to reach a listening debuggee gives up after this time
+pub const CONNECT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// pause after a failed accept, a listener that keeps failing must not spin
+pub const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
 pub struct DebuggerConnection {
-    read_stream: Option<OwnedReadHalf>,
-    write_stream: Option<Arc<Mutex<OwnedWriteHalf>>>,
+    read_stream: Option<LinkReader>,
     reader_task: Option<JoinHandle<()>>,
     response_senders: Arc<Mutex<HashMap<MessageCMD, mpsc::Sender<Message>>>>,
-    eval_seq_id: i64,
-    eval_response: Arc<Mutex<HashMap<i64, mpsc::Sender<EvalRsp>>>>,
+    /// write half of the link with what round trips need
+    requests: DebuggerRequests,
+    /// larger messages from the debuggee are dropped
+    max_message_size: usize,
+    /// losing this connection terminates the debug session
+    closes_session: bool,
+    /// told when the link drops, the session is then kept for a reconnect
+    disconnect_sender: Option<mpsc::Sender<()>>,
+    /// encrypts the links opened by `connect`
+    tls: Option<TlsConnector>,
+}
+
+impl std::fmt::Debug for DebuggerConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DebuggerConnection")
+            .field("connected", &self.is_connected())
+            .field("request_timeout", &self.requests.request_timeout)
+            .field("closes_session", &self.closes_session)
+            .field("tls", &self.tls.is_some())
+            .finish_non_exhaustive()
+    }
 }
 
 #[allow(unused)]
@@ -39,67 +89,97 @@ impl DebuggerConnection {
     pub fn new() -> Self {
         DebuggerConnection {
             read_stream: None,
-            write_stream: None,
             reader_task: None,
             response_senders: Arc::new(Mutex::new(HashMap::new())),
-            eval_seq_id: 0,
-            eval_response: Arc::new(Mutex::new(HashMap::new())),
+            requests: DebuggerRequests::new(DEFAULT_REQUEST_TIMEOUT),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            closes_session: true,
+            disconnect_sender: None,
+            tls: None,
         }
     }
 
-    pub async fn connect(&mut self, addr: &str, timeout_secs: Option<u64>) -> DebuggerResult<()> {
-        let addr: SocketAddr = addr.parse().map_err(|e| DebuggerError::from(e))?;
-
-        let stream = if let Some(secs) = timeout_secs {
-            let connect_future = TcpStream::connect(addr);
-            match timeout(Duration::from_secs(secs), connect_future).await {
-                Ok(result) => result.map_err(|e| DebuggerError::from(e))?,
-                Err(_) => {
-                    return Err(DebuggerError::ConnectionError(format!(
-                        "connect {} timeout",
-                        addr
-                    ))
-                    .into());
-                }
-            }
-        } else {
-            TcpStream::connect(addr)
-                .await
-                .map_err(|e| DebuggerError::from(e))?
-        };
-        let (read_stream, write_stream) = stream.into_split();
-        self.read_stream = Some(read_stream);
-        self.write_stream = Some(Arc::new(Mutex::new(write_stream)));
+    /// Extra targets of a session may go away without ending it.
+    pub fn set_closes_session(&mut self, closes_session: bool) {
+        self.closes_session = closes_session;
+    }
+
+    pub fn set_request_timeout(&mut self, request_timeout: Duration) {
+        self.requests.request_timeout = request_timeout;
+    }
+
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = max_message_size;
+    }
+
+    pub fn set_tls(&mut self, tls: Option<TlsConnector>) {
+        self.tls = tls;
+    }
+
+    pub fn tls(&self) -> Option<TlsConnector> {
+        self.tls.clone()
+    }
+
+    /// Report lost links instead of terminating the session.
+    pub fn watch_disconnect(&mut self) -> mpsc::Receiver<()> {
+        let (sender, receiver) = mpsc::channel(1);
+        self.disconnect_sender = Some(sender);
+        receiver
+    }
+
+    /// Take over the link of a fresh connection. Registered callbacks stay, so the
+    /// notification handlers keep working after a reconnect.
+    pub fn reattach(&mut self, mut link: DebuggerConnection) {
+        if let Some(handle) = self.reader_task.take() {
+            handle.abort();
+        }
+        self.requests.correlator.lock().unwrap().fail_all();
+        self.read_stream = link.read_stream.take();
+        self.requests.write_stream = link.requests.write_stream.take();
+    }
+
+    pub async fn connect(
+        &mut self,
+        endpoint: &DebuggerEndpoint,
+        connect_timeout: Option<Duration>,
+    ) -> DebuggerResult<()> {
+        let (read_stream, write_stream) =
+            transport::open(endpoint, connect_timeout, self.tls.as_ref()).await?;
+        self.attach(read_stream, write_stream);
         Ok(())
     }
 
-    pub async fn listen(&mut self, addr: &str) -> DebuggerResult<()> {
-        let addr: SocketAddr = addr.parse().map_err(|e| DebuggerError::from(e))?;
-        let listener = TcpListener::bind(addr)
-            .await
-            .map_err(|e| DebuggerError::from(e))?;
+    pub async fn bind(
+        endpoint: &DebuggerEndpoint,
+        tls: Option<TlsAcceptor>,
+    ) -> DebuggerResult<DebuggerListener> {
+        DebuggerListener::bind(endpoint, tls).await
+    }
 
-        let (stream, _) = listener
-            .accept()
-            .await
-            .map_err(|e| DebuggerError::from(e))?;
+    /// Wait for a debuggee on the listener, returns a description of the peer.
+    pub async fn accept(&mut self, listener: &DebuggerListener) -> DebuggerResult<String> {
+        let (read_stream, write_stream, peer) = listener.accept().await?;
+        self.attach(read_stream, write_stream);
+        Ok(peer)
+    }
 
-        let (read_stream, write_stream) = stream.into_split();
+    fn attach(&mut self, read_stream: LinkReader, write_stream: LinkWriter) {
         self.read_stream = Some(read_stream);
-        self.write_stream = Some(Arc::new(Mutex::new(write_stream)));
-        Ok(())
+        let write_stream = FramedWrite::new(write_stream, EmmyCodec::new(self.max_message_size));
+        self.requests.write_stream = Some(Arc::new(Mutex::new(write_stream)));
     }
 
     pub fn is_connected(&self) -> bool {
-        self.write_stream.is_some()
+        self.requests.write_stream.is_some()
     }
 
     pub async fn close(&mut self) {
         if let Some(handle) = self.reader_task.take() {
             handle.abort();
         }
+        self.requests.correlator.lock().unwrap().fail_all();
         self.read_stream = None;
-        self.write_stream = None;
+        self.requests.write_stream = None;
     }
 
     pub fn start_reader_task(&mut self, ide_conn: Arc<std::sync::Mutex<ServerOutput<Stdout>>>) {
@@ -110,44 +190,24 @@ impl DebuggerConnection {
         let read_stream = self.read_stream.take();
         if let Some(stream) = read_stream {
             let senders = self.response_senders.clone();
-            let eval_response = self.eval_response.clone();
+            let correlator = self.requests.correlator.clone();
+            let closes_session = self.closes_session && self.disconnect_sender.is_none();
+            let disconnect_sender = self.disconnect_sender.clone();
+
+            let max_message_size = self.max_message_size;
 
             let handle = tokio::spawn(async move {
-                let mut msg_id_string = String::new();
-                let mut msg_json_string = String::new();
-                let mut reader = BufReader::new(stream);
+                let mut frames = FramedRead::new(stream, EmmyCodec::new(max_message_size));
                 loop {
-                    msg_id_string.clear();
-                    msg_json_string.clear();
-
-                    match reader.read_line(&mut msg_id_string).await {
-                        Ok(0) => {
-                            log::error!("Connection closed by peer");
-                            let mut ide_conn = ide_conn.lock().unwrap();
-                            ide_conn.send_event(Event::Output(OutputEventBody {
-                                category: Some(dap::types::OutputEventCategory::Console),
-                                output: "Disconnected\n".to_string(),
-                                ..Default::default()
-                            }));
-
-                            ide_conn.send_event(Event::Terminated(None));
-                            break;
-                        }
-                        Ok(n) => {
-                            if n == 0 {
-                                break;
-                            }
+                    match frames.next().await {
+                        Some(Ok(message)) => {
+                            Self::dispatch_message(message, &senders, &correlator).await;
                         }
-                        Err(e) => {
+                        Some(Err(e)) => {
                             log::error!("Error reading from stream: {}", e);
                             break;
                         }
-                    }
-
-                    log::debug!("read message id {}", msg_id_string);
-
-                    match reader.read_line(&mut msg_json_string).await {
-                        Ok(0) => {
+                        None => {
                             log::error!("Connection closed by peer");
                             let mut ide_conn = ide_conn.lock().unwrap();
                             ide_conn.send_event(Event::Output(OutputEventBody {
@@ -156,42 +216,18 @@ impl DebuggerConnection {
                                 ..Default::default()
                             }));
 
-                            ide_conn.send_event(Event::Terminated(None));
-                            break;
-                        }
-                        Ok(n) => {
-                            if n == 0 {
-                                break;
+                            if closes_session {
+                                ide_conn.send_event(Event::Terminated(None));
                             }
-                        }
-                        Err(e) => {
-                            log::error!("Error reading from stream: {}", e);
                             break;
                         }
                     }
+                }
+                log::info!("Debug channel closed: {}", frames.decoder().stats());
 
-                    log::debug!("read message json {}", msg_json_string);
-
-                    let msg_id = match msg_id_string.trim().parse::<i32>() {
-                        Ok(id) => id,
-                        Err(e) => {
-                            log::error!("Error parsing message ID: {}", e);
-                            continue;
-                        }
-                    };
-
-                    let message = match Message::from_str(
-                        &msg_json_string,
-                        MessageCMD::from(msg_id as i64),
-                    ) {
-                        Ok(msg) => msg,
-                        Err(e) => {
-                            log::error!("Error parsing message JSON: {}", e);
-                            continue;
-                        }
-                    };
-
-                    Self::dispatch_message(message, &senders, &eval_response).await;
+                correlator.lock().unwrap().fail_all();
+                if let Some(sender) = disconnect_sender {
+                    let _ = sender.try_send(());
                 }
             });
 
@@ -202,26 +238,16 @@ impl DebuggerConnection {
     async fn dispatch_message(
         message: Message,
         senders: &Arc<Mutex<HashMap<MessageCMD, mpsc::Sender<Message>>>>,
-        eval_response: &Arc<Mutex<HashMap<i64, mpsc::Sender<EvalRsp>>>>,
+        correlator: &Arc<std::sync::Mutex<RequestCorrelator>>,
     ) {
         let cmd = message.get_cmd();
         log::info!("dispatch message: {:?}", cmd);
-        match cmd {
-            MessageCMD::EvalRsp => {
-                if let Message::EvalRsp(eval_rsp) = message {
-                    let seq = eval_rsp.seq as i64;
-                    log::info!("response seq: {}", seq);
-                    let mut senders_guard = eval_response.lock().await;
-                    if let Some(sender) = senders_guard.remove(&seq) {
-                        let _ = sender.send(eval_rsp).await;
-                    }
-                }
-            }
-            _ => {
-                let senders_guard = senders.lock().await;
-                if let Some(sender) = senders_guard.get(&cmd) {
-                    let _ = sender.send(message).await;
-                }
+        // responses go to their request, everything else to the registered callbacks
+        let resolved = correlator.lock().unwrap().resolve(message);
+        if let Some(message) = resolved {
+            let senders_guard = senders.lock().await;
+            if let Some(sender) = senders_guard.get(&cmd) {
+                let _ = sender.send(message).await;
             }
         }
     }
@@ -239,186 +265,15 @@ impl DebuggerConnection {
         Some(rx)
     }
 
-    async fn register_eval_callback(&self, seq: i64) -> Option<mpsc::Receiver<EvalRsp>> {
-        if !self.is_connected() {
-            return None;
-        }
-
-        let (tx, rx) = mpsc::channel(1); // 创建容量为32的通道
-
-        let mut senders_guard = self.eval_response.lock().await;
-        senders_guard.insert(seq, tx);
-
-        Some(rx)
-    }
-
+    /// Fire and forget, requests waiting for an answer go through `requests`.
     pub async fn send_message(&self, message: Message) -> DebuggerResult<()> {
-        if let Some(stream) = &self.write_stream {
-            let mut stream_guard = stream.lock().await;
-            let json = match serde_json::to_string(&message) {
-                Ok(json) => json,
-                Err(e) => {
-                    log::error!("serde fail: {}", e);
-                    return Err(
-                        DebuggerError::SerializationError(format!("serde fail: {}", e)).into(),
-                    );
-                }
-            };
-
-            let msg_id = message.get_cmd() as i32;
-            let message_text = format!("{}\n{}\n", msg_id, json);
-            log::debug!("send message: {}", message_text);
-            match stream_guard
-                .write_all(message_text.as_bytes())
-                .await
-                .map_err(|e| DebuggerError::IoError(e).into())
-            {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("send message fail: {}", e);
-                    return Err(e);
-                }
-            }
-            log::debug!("send message ok");
-            match stream_guard
-                .flush()
-                .await
-                .map_err(|e| DebuggerError::IoError(e).into())
-            {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("flush stream fail: {}", e);
-                    return Err(e);
-                }
-            }
-            log::debug!("flush stream ok");
-            Ok(())
-        } else {
-            Err(DebuggerError::ConnectionError("not connected".to_string()).into())
-        }
+        self.requests.send_message(message).await
     }
 
-    pub async fn send_request(&self, request: Message) -> DebuggerResult<Message> {
-        if let Some(stream) = &self.write_stream {
-            let mut stream_guard = stream.lock().await;
-            let json = match serde_json::to_string(&request) {
-                Ok(json) => json,
-                Err(e) => {
-                    log::error!("serde fail: {}", e);
-                    return Err(
-                        DebuggerError::SerializationError(format!("serde fail: {}", e)).into(),
-                    );
-                }
-            };
-
-            let msg_id = request.get_cmd() as i32;
-            let message_text = format!("{}\n{}\n", msg_id, json);
-            log::debug!("send message: {}", message_text);
-            match stream_guard
-                .write_all(message_text.as_bytes())
-                .await
-                .map_err(|e| DebuggerError::IoError(e).into())
-            {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("send message fail: {}", e);
-                    return Err(e);
-                }
-            }
-            log::debug!("send message ok");
-
-            match stream_guard
-                .flush()
-                .await
-                .map_err(|e| DebuggerError::IoError(e).into())
-            {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("flush stream fail: {}", e);
-                    return Err(e);
-                }
-            }
-
-            log::debug!("flush stream ok");
-
-            drop(stream_guard);
-            // 等待响应
-            let receiver = self
-                .register_callback(request.get_cmd().get_rsp_cmd())
-                .await;
-            if let Some(mut rx) = receiver {
-                if let Some(response) = rx.recv().await {
-                    return Ok(response);
-                }
-            }
-        }
-
-        Err(DebuggerError::ConnectionError("not connected".to_string()).into())
-    }
-
-    pub async fn eval_expr(
-        &mut self,
-        expression: String,
-        cache_id: i64,
-        depth: i64,
-        frame_id: i64,
-    ) -> DebuggerResult<EvalRsp> {
-        if let Some(stream) = &self.write_stream {
-            let seq = self.eval_seq_id;
-            self.eval_seq_id += 1;
-            let eval_req = EvalReq {
-                cmd: MessageCMD::EvalReq as i64,
-                seq: seq as i32,
-                expr: expression.clone(),
-                stack_level: frame_id as i32,
-                depth: depth as i32,
-                cache_id: cache_id as i32,
-                value: None,
-                set_value: None,
-            };
-
-            let mut stream_guard = stream.lock().await;
-            let json = serde_json::to_string(&eval_req)
-                .map_err(|e| DebuggerError::SerializationError(format!("serde fail: {}", e)))?;
-
-            let msg_id = MessageCMD::EvalReq as i32;
-            let message_text = format!("{}\n{}\n", msg_id, json);
-            log::info!("request eval :{}, seq: {}", expression, seq);
-            let receiver = self.register_eval_callback(seq).await;
-
-            match stream_guard
-                .write_all(message_text.as_bytes())
-                .await
-                .map_err(|e| DebuggerError::IoError(e).into())
-            {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("send message fail: {}", e);
-                    return Err(e);
-                }
-            }
-
-            match stream_guard
-                .flush()
-                .await
-                .map_err(|e| DebuggerError::IoError(e).into())
-            {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("flush stream fail: {}", e);
-                    return Err(e);
-                }
-            }
-
-            drop(stream_guard);
-            if let Some(mut rx) = receiver {
-                if let Some(response) = rx.recv().await {
-                    return Ok(response);
-                }
-            }
-        }
-
-        Err(DebuggerError::ConnectionError("not connected".to_string()).into())
+    /// Handle for round trips, taken under the connection lock and used after it is
+    /// released so a slow answer does not block other users of the connection.
+    pub fn requests(&self) -> DebuggerRequests {
+        self.requests.clone()
     }
 }
 
@@ -432,4 +287,87 @@ pub struct DebuggerData {
     pub cache: DebuggerCache,
     pub breakpoints: HashMap<(String, i64), BreakPoint>,
     pub breakpoint_id: i64,
+    /// column of the inline breakpoint of a line, the debuggee only knows the line
+    pub breakpoint_columns: HashMap<(String, i64), i64>,
+    /// lines of resolved source files
+    pub source_cache: HashMap<String, Vec<String>>,
+    /// locations offered by gotoTargets, keyed by goto target id
+    pub goto_targets: HashMap<i64, BreakPoint>,
+    /// one-shot breakpoint of a pending run to line, never reported to the editor
+    pub run_to_breakpoint: Option<BreakPoint>,
+    pub track_coroutines: bool,
+    /// chunk name globs of library code, their frames are collapsed in the call stack
+    pub library_files: Vec<glob::Pattern>,
+    /// chunk name globs stepping never stops in
+    pub skip_files: Vec<glob::Pattern>,
+    /// step the user asked for, cleared once it lands in user code
+    pub step_action: Option<DebugAction>,
+    /// steps issued by the adapter since the user's step
+    pub skipped_steps: usize,
+    /// callee names offered by the last stepInTargets, the target id is the index + 1
+    pub step_in_targets: Vec<String>,
+    pub step_in_goal: Option<StepInGoal>,
+    /// recorded stops for stepBack and reverseContinue
+    pub history: StopHistory,
+    /// counts the stops reported to the editor
+    pub stop_seq: u64,
+    /// values of watch expressions, keyed by expression
+    pub watch_values: HashMap<String, WatchValue>,
+    pub coroutine_tracker_installed: bool,
+    pub coroutines: Vec<LuaCoroutine>,
+    pub coroutine_ids: CoroutineThreadIds,
+    pub stopped_thread_id: i64,
+    /// column of the inline breakpoint the live stop came from, the debuggee only
+    /// reports lines
+    pub stop_column: Option<i64>,
+    /// stacks of coroutines other than the stopped one, fetched on demand
+    pub thread_stacks: HashMap<i64, Vec<Stack>>,
+}
+
+impl DebuggerData {
+    /// The debuggee has a stop shown in the editor and answers evals.
+    pub fn is_stopped(&self) -> bool {
+        !self.stacks.is_empty()
+    }
+
+    /// The debuggee runs again, what was fetched for the stop is stale.
+    pub fn resume(&mut self) {
+        self.stacks.clear();
+        self.thread_stacks.clear();
+        self.coroutines.clear();
+        self.cache.clear();
+        self.stop_column = None;
+    }
+
+    /// Fresh data for another target of the same session, only the launch configuration
+    /// and the breakpoints are shared.
+    pub fn clone_config(&self) -> DebuggerData {
+        let mut data = DebuggerData {
+            file_cache: self.file_cache.clone(),
+            extension: self.extension.clone(),
+            sources: self.sources.clone(),
+            breakpoints: self.breakpoints.clone(),
+            breakpoint_id: self.breakpoint_id,
+            breakpoint_columns: self.breakpoint_columns.clone(),
+            track_coroutines: self.track_coroutines,
+            library_files: self.library_files.clone(),
+            skip_files: self.skip_files.clone(),
+            history: StopHistory::new(self.history.size, self.history.depth),
+            ..Default::default()
+        };
+        data.cache.max_string_length = self.cache.max_string_length;
+        data
+    }
+
+    pub fn get_stack(&self, frame_id: i64) -> Option<&Stack> {
+        match split_thread_frame_id(frame_id) {
+            Some((thread_id, level)) => self
+                .thread_stacks
+                .get(&thread_id)
+                .and_then(|stacks| stacks.get(level as usize)),
+            None => usize::try_from(frame_id)
+                .ok()
+                .and_then(|level| self.stacks.get(level)),
+        }
+    }
 }
@@ -126,8 +126,10 @@ impl Message {
         }
     }
 
-    pub fn from_str(s: &str, cmd: MessageCMD) -> Result<Self, serde_json::Error> {
-        let value: serde_json::Value = serde_json::from_str(s)?;
+    pub fn from_value(
+        value: serde_json::Value,
+        cmd: MessageCMD,
+    ) -> Result<Self, serde_json::Error> {
         match cmd {
             MessageCMD::InitReq => {
                 let init_req: InitReq = serde_json::from_value(value).map_err(de::Error::custom)?;
@@ -0,0 +1,183 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicI64, Ordering},
+    },
+    time::Duration,
+};
+
+use futures_util::SinkExt;
+use tokio::{
+    sync::{Mutex, oneshot},
+    time::timeout,
+};
+use tokio_util::{codec::FramedWrite, sync::CancellationToken};
+
+use super::{
+    DebuggerError, DebuggerResult, EvalReq, EvalRsp, LinkWriter, Message, MessageCMD,
+    codec::EmmyCodec, correlator::RequestCorrelator,
+};
+
+/// Round trips to the debuggee. Cheap to clone, a handle keeps working on its link while
+/// the connection is locked or reattached by others.
+#[derive(Clone)]
+pub struct DebuggerRequests {
+    pub(super) write_stream: Option<Arc<Mutex<FramedWrite<LinkWriter, EmmyCodec>>>>,
+    /// requests waiting for their response
+    pub(super) correlator: Arc<std::sync::Mutex<RequestCorrelator>>,
+    eval_seq_id: Arc<AtomicI64>,
+    /// how long a request waits for the debuggee
+    pub(super) request_timeout: Duration,
+}
+
+impl DebuggerRequests {
+    pub(super) fn new(request_timeout: Duration) -> Self {
+        DebuggerRequests {
+            write_stream: None,
+            correlator: Arc::new(std::sync::Mutex::new(RequestCorrelator::default())),
+            eval_seq_id: Arc::new(AtomicI64::new(0)),
+            request_timeout,
+        }
+    }
+
+    pub async fn send_message(&self, message: Message) -> DebuggerResult<()> {
+        if let Some(stream) = &self.write_stream {
+            let mut stream_guard = stream.lock().await;
+            // the codec writes and flushes the frame
+            if let Err(e) = stream_guard.send(message).await {
+                log::error!("send message fail: {}", e);
+                return Err(e.into());
+            }
+            log::debug!("send message ok");
+            Ok(())
+        } else {
+            Err(DebuggerError::ConnectionError("not connected".to_string()).into())
+        }
+    }
+
+    /// Send a request and wait for its response, the wait ends early when the
+    /// debuggee does not answer in time or `cancel` fires.
+    pub async fn send_request(
+        &self,
+        request: Message,
+        cancel: &CancellationToken,
+    ) -> DebuggerResult<Message> {
+        let rsp_cmd = request.get_cmd().get_rsp_cmd();
+        // registered before the write, a fast answer must find its waiter
+        let (id, receiver) = self.correlator.lock().unwrap().expect_response(rsp_cmd);
+        if let Err(e) = self.send_message(request).await {
+            self.correlator.lock().unwrap().forget_response(rsp_cmd, id);
+            return Err(e);
+        }
+
+        let result = self.wait_response(rsp_cmd, receiver, cancel).await;
+        if result.is_err() {
+            // a late answer may still come, it gets one more timeout to arrive
+            self.correlator
+                .lock()
+                .unwrap()
+                .abandon_response(rsp_cmd, id, self.request_timeout);
+        }
+        result
+    }
+
+    async fn wait_response<T>(
+        &self,
+        rsp_cmd: MessageCMD,
+        receiver: oneshot::Receiver<T>,
+        cancel: &CancellationToken,
+    ) -> DebuggerResult<T> {
+        tokio::select! {
+            result = timeout(self.request_timeout, receiver) => match result {
+                Ok(Ok(response)) => Ok(response),
+                Ok(Err(_)) => Err(DebuggerError::ConnectionError(format!(
+                    "connection lost while waiting for {:?}",
+                    rsp_cmd
+                ))
+                .into()),
+                Err(_) => Err(DebuggerError::Timeout(format!(
+                    "no {:?} from the debuggee within {} ms",
+                    rsp_cmd,
+                    self.request_timeout.as_millis()
+                ))
+                .into()),
+            },
+            _ = cancel.cancelled() => Err(DebuggerError::Cancelled.into()),
+        }
+    }
+
+    pub async fn eval_expr(
+        &self,
+        expression: String,
+        cache_id: i64,
+        depth: i64,
+        frame_id: i64,
+        cancel: &CancellationToken,
+    ) -> DebuggerResult<EvalRsp> {
+        self.send_eval_req(
+            EvalReq {
+                cmd: MessageCMD::EvalReq as i64,
+                seq: 0,
+                expr: expression,
+                stack_level: frame_id as i32,
+                depth: depth as i32,
+                cache_id: cache_id as i32,
+                value: None,
+                set_value: None,
+            },
+            cancel,
+        )
+        .await
+    }
+
+    /// Assign the result of `value` to `expression` (a local, upvalue, global or field)
+    /// in the given frame.
+    pub async fn set_value(
+        &self,
+        expression: String,
+        value: String,
+        frame_id: i64,
+        cancel: &CancellationToken,
+    ) -> DebuggerResult<EvalRsp> {
+        self.send_eval_req(
+            EvalReq {
+                cmd: MessageCMD::EvalReq as i64,
+                seq: 0,
+                expr: expression,
+                stack_level: frame_id as i32,
+                depth: 1,
+                cache_id: 0,
+                value: Some(value),
+                set_value: Some(true),
+            },
+            cancel,
+        )
+        .await
+    }
+
+    async fn send_eval_req(
+        &self,
+        mut eval_req: EvalReq,
+        cancel: &CancellationToken,
+    ) -> DebuggerResult<EvalRsp> {
+        if self.write_stream.is_none() {
+            return Err(DebuggerError::ConnectionError("not connected".to_string()).into());
+        }
+        let seq = self.eval_seq_id.fetch_add(1, Ordering::Relaxed);
+        eval_req.seq = seq as i32;
+        log::info!("request eval :{}, seq: {}", eval_req.expr, seq);
+
+        let receiver = self.correlator.lock().unwrap().expect_eval(seq);
+        let result = match self.send_message(Message::EvalReq(eval_req)).await {
+            Ok(_) => {
+                self.wait_response(MessageCMD::EvalRsp, receiver, cancel)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            self.correlator.lock().unwrap().forget_eval(seq);
+        }
+        result
+    }
+}
@@ -0,0 +1,10 @@
+/// A step into one of several calls on a line, chosen through stepInTargets.
+#[derive(Debug, Clone)]
+pub struct StepInGoal {
+    /// name the callee reports in its stack frame
+    pub function_name: String,
+    /// stack depth of the frame the step started from
+    pub depth: usize,
+    /// steps the adapter may still issue before giving up
+    pub steps_left: usize,
+}
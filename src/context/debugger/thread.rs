@@ -0,0 +1,114 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::context::target::TARGET_THREAD_STRIDE;
+
+pub const MAIN_THREAD_ID: i64 = 1;
+
+/// Frames of the thread that hit the break use their level as id, frames fetched
+/// for other threads are offset by `thread_id * FRAME_ID_STRIDE`.
+pub const FRAME_ID_STRIDE: i64 = 100_000;
+
+/// A coroutine seen by the coroutine tracker installed in the debuggee.
+#[derive(Debug, Clone)]
+pub struct LuaCoroutine {
+    pub thread_id: i64,
+    /// id given by the tracker, shown to the user
+    pub tracker_id: i64,
+    pub status: String,
+    /// where the coroutine body was defined, `file:line`
+    pub source: String,
+}
+
+pub fn thread_frame_id(thread_id: i64, level: i64) -> i64 {
+    thread_id * FRAME_ID_STRIDE + level
+}
+
+/// Split a frame id of a non stopped thread into thread id and level.
+pub fn split_thread_frame_id(frame_id: i64) -> Option<(i64, i64)> {
+    if frame_id < FRAME_ID_STRIDE {
+        return None;
+    }
+    Some((frame_id / FRAME_ID_STRIDE, frame_id % FRAME_ID_STRIDE))
+}
+
+/// Dap thread ids of tracked coroutines. Tracker ids only grow while thread ids have to
+/// stay inside the target's range, so ids of dead coroutines are handed out again.
+#[derive(Debug, Default)]
+pub struct CoroutineThreadIds {
+    by_tracker: HashMap<i64, i64>,
+    by_thread: HashMap<i64, i64>,
+    next_thread_id: i64,
+}
+
+impl CoroutineThreadIds {
+    /// Thread id of a coroutine, `None` when every id is taken by a live coroutine.
+    pub fn thread_id(&mut self, tracker_id: i64) -> Option<i64> {
+        if let Some(thread_id) = self.by_tracker.get(&tracker_id) {
+            return Some(*thread_id);
+        }
+        let first = MAIN_THREAD_ID + 1;
+        if self.by_thread.len() as i64 >= TARGET_THREAD_STRIDE - first {
+            return None;
+        }
+        // round robin, an id the editor still shows is not reused right away
+        let mut thread_id = self.next_thread_id.max(first);
+        loop {
+            if thread_id >= TARGET_THREAD_STRIDE {
+                thread_id = first;
+            }
+            if !self.by_thread.contains_key(&thread_id) {
+                break;
+            }
+            thread_id += 1;
+        }
+        self.next_thread_id = thread_id + 1;
+        self.by_tracker.insert(tracker_id, thread_id);
+        self.by_thread.insert(thread_id, tracker_id);
+        Some(thread_id)
+    }
+
+    pub fn tracker_id(&self, thread_id: i64) -> Option<i64> {
+        self.by_thread.get(&thread_id).copied()
+    }
+
+    /// Free the ids of coroutines which are no longer alive.
+    pub fn retain(&mut self, live: &HashSet<i64>) {
+        self.by_tracker
+            .retain(|tracker_id, _| live.contains(tracker_id));
+        self.by_thread
+            .retain(|_, tracker_id| live.contains(tracker_id));
+    }
+
+    pub fn clear(&mut self) {
+        *self = CoroutineThreadIds::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_coroutines_to_thread_ids() {
+        let mut ids = CoroutineThreadIds::default();
+        assert_eq!(ids.thread_id(7), Some(2));
+        assert_eq!(ids.thread_id(9), Some(3));
+        assert_eq!(ids.thread_id(7), Some(2));
+        assert_eq!(ids.tracker_id(3), Some(9));
+
+        ids.retain(&HashSet::from([9]));
+        assert_eq!(ids.tracker_id(2), None);
+        // freed ids come back only after the rest of the range
+        assert_eq!(ids.thread_id(10), Some(4));
+    }
+
+    #[test]
+    fn stays_inside_the_target_range() {
+        let mut ids = CoroutineThreadIds {
+            next_thread_id: TARGET_THREAD_STRIDE - 1,
+            ..Default::default()
+        };
+        assert_eq!(ids.thread_id(1_000_001), Some(TARGET_THREAD_STRIDE - 1));
+        assert_eq!(ids.thread_id(1_000_002), Some(MAIN_THREAD_ID + 1));
+    }
+}
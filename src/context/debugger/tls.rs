@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use tokio_rustls::{
+    TlsAcceptor, TlsConnector,
+    rustls::{
+        ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{self, WebPkiSupportedAlgorithms},
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime, pem::PemObject},
+        server::WebPkiClientVerifier,
+    },
+};
+
+use super::{DebuggerEndpoint, DebuggerError, DebuggerResult};
+use crate::context::TlsOptions;
+
+/// Client side of an encrypted debug channel, the adapter connects to the debuggee.
+pub fn tls_connector(options: &TlsOptions) -> DebuggerResult<TlsConnector> {
+    let builder = ClientConfig::builder();
+    let builder = if options.insecure_skip_verify {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(SkipServerVerification::new()))
+    } else {
+        let Some(ca) = &options.ca else {
+            return Err(DebuggerError::TlsError(
+                "a `ca` is needed to verify the debuggee, or set `insecureSkipVerify`".to_string(),
+            )
+            .into());
+        };
+        builder.with_root_certificates(load_roots(ca)?)
+    };
+    // with a certificate of its own the adapter can pass a relay that checks clients
+    let config = match (&options.cert, &options.key) {
+        (Some(cert), Some(key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(tls_error)?,
+        _ => builder.with_no_client_auth(),
+    };
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Server side of an encrypted debug channel, the debuggee connects to the adapter.
+/// With a `ca` only debuggees presenting a certificate it signed are accepted.
+pub fn tls_acceptor(options: &TlsOptions) -> DebuggerResult<TlsAcceptor> {
+    let (Some(cert), Some(key)) = (&options.cert, &options.key) else {
+        return Err(DebuggerError::TlsError(
+            "accepting encrypted connections needs a `cert` and a `key`".to_string(),
+        )
+        .into());
+    };
+    let builder = ServerConfig::builder();
+    let builder = match &options.ca {
+        Some(ca) => {
+            let verifier = WebPkiClientVerifier::builder(Arc::new(load_roots(ca)?))
+                .build()
+                .map_err(|e| DebuggerError::TlsError(e.to_string()))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .map_err(tls_error)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Name the debuggee's certificate is checked against, the host it was reached by.
+pub fn server_name(endpoint: &DebuggerEndpoint) -> DebuggerResult<ServerName<'static>> {
+    let host = match endpoint {
+        DebuggerEndpoint::Tcp(addr) => addr
+            .rsplit_once(':')
+            .map_or(addr.as_str(), |(host, _)| host)
+            .trim_start_matches('[')
+            .trim_end_matches(']'),
+        // local transports have no host, a certificate for localhost fits them
+        _ => "localhost",
+    };
+    ServerName::try_from(host.to_string())
+        .map_err(|e| DebuggerError::TlsError(format!("invalid server name {}: {}", host, e)).into())
+}
+
+fn load_certs(path: &str) -> DebuggerResult<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| DebuggerError::TlsError(format!("failed to read {}: {}", path, e)))?;
+    if certs.is_empty() {
+        return Err(DebuggerError::TlsError(format!("{} holds no certificate", path)).into());
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> DebuggerResult<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|e| DebuggerError::TlsError(format!("failed to read {}: {}", path, e)).into())
+}
+
+fn load_roots(path: &str) -> DebuggerResult<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(tls_error)?;
+    }
+    Ok(roots)
+}
+
+fn tls_error(err: tokio_rustls::rustls::Error) -> DebuggerError {
+    DebuggerError::TlsError(err.to_string())
+}
+
+/// `insecureSkipVerify`: any certificate is taken, the handshake signatures are still
+/// checked so the channel stays encrypted.
+#[derive(Debug)]
+struct SkipServerVerification {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl SkipServerVerification {
+    fn new() -> Self {
+        SkipServerVerification {
+            algorithms: crypto::ring::default_provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
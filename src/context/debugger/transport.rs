@@ -0,0 +1,494 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    task::JoinSet,
+    time::timeout,
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use super::{DebuggerError, DebuggerResult, auth, auth::PeerGuard, tls};
+
+pub type LinkReader = Box<dyn AsyncRead + Send + Sync + Unpin>;
+pub type LinkWriter = Box<dyn AsyncWrite + Send + Sync + Unpin>;
+
+/// Any connected byte stream, split into reader and writer once the link is up.
+pub trait LinkStream: AsyncRead + AsyncWrite + Send + Sync + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin> LinkStream for T {}
+
+type Link = Box<dyn LinkStream>;
+
+/// head start of a connection attempt before the next address is tried as well
+const CONNECT_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// a peer that does not finish the tls or secret handshake in this time is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the debuggee is reached, every transport carries the same framing.
+#[derive(Debug, Clone)]
+pub enum DebuggerEndpoint {
+    /// `host:port`
+    Tcp(String),
+    /// unix domain socket path
+    Unix(String),
+    /// descriptor of a connected socket inherited from the parent process
+    Fd(i32),
+    /// windows named pipe, `\\.\pipe\name`
+    Pipe(String),
+}
+
+impl fmt::Display for DebuggerEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DebuggerEndpoint::Tcp(addr) => write!(f, "{}", addr),
+            DebuggerEndpoint::Unix(path) => write!(f, "unix:{}", path),
+            DebuggerEndpoint::Fd(fd) => write!(f, "fd:{}", fd),
+            DebuggerEndpoint::Pipe(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Open a link to a listening debuggee, encrypted when a connector is given.
+pub async fn open(
+    endpoint: &DebuggerEndpoint,
+    connect_timeout: Option<Duration>,
+    tls: Option<&TlsConnector>,
+) -> DebuggerResult<(LinkReader, LinkWriter)> {
+    let link = open_plain(endpoint, connect_timeout).await?;
+    let Some(connector) = tls else {
+        return Ok(split_link(link));
+    };
+
+    let handshake = connector.connect(tls::server_name(endpoint)?, link);
+    let stream = timeout(connect_timeout.unwrap_or(HANDSHAKE_TIMEOUT), handshake)
+        .await
+        .map_err(|_| DebuggerError::TlsError(format!("handshake with {} timed out", endpoint)))?
+        .map_err(|e| {
+            DebuggerError::TlsError(format!("handshake with {} failed: {}", endpoint, e))
+        })?;
+    Ok(split_link(Box::new(stream)))
+}
+
+fn split_link(link: Link) -> (LinkReader, LinkWriter) {
+    let (read_stream, write_stream) = tokio::io::split(link);
+    (Box::new(read_stream), Box::new(write_stream))
+}
+
+async fn open_plain(
+    endpoint: &DebuggerEndpoint,
+    connect_timeout: Option<Duration>,
+) -> DebuggerResult<Link> {
+    let opening = open_link(endpoint, connect_timeout);
+    match connect_timeout {
+        // tcp times out address by address, so its error names every address tried
+        Some(connect_timeout) if !matches!(endpoint, DebuggerEndpoint::Tcp(_)) => {
+            match timeout(connect_timeout, opening).await {
+                Ok(result) => result,
+                Err(_) => Err(DebuggerError::ConnectionError(format!(
+                    "connect {} timeout",
+                    endpoint
+                ))
+                .into()),
+            }
+        }
+        _ => opening.await,
+    }
+}
+
+async fn open_link(
+    endpoint: &DebuggerEndpoint,
+    connect_timeout: Option<Duration>,
+) -> DebuggerResult<Link> {
+    match endpoint {
+        DebuggerEndpoint::Tcp(addr) => Ok(Box::new(connect_tcp(addr, connect_timeout).await?)),
+        #[cfg(unix)]
+        DebuggerEndpoint::Unix(path) => {
+            let stream = tokio::net::UnixStream::connect(path)
+                .await
+                .map_err(DebuggerError::from)?;
+            Ok(Box::new(stream))
+        }
+        #[cfg(unix)]
+        DebuggerEndpoint::Fd(fd) => {
+            use std::os::fd::FromRawFd;
+            check_inherited_socket(*fd)?;
+            // SAFETY: the descriptor is an open socket the launch configuration hands over
+            // to the adapter, it is taken once as the endpoint is never retried
+            let stream = unsafe { std::os::unix::net::UnixStream::from_raw_fd(*fd) };
+            stream.set_nonblocking(true).map_err(DebuggerError::from)?;
+            let stream = tokio::net::UnixStream::from_std(stream).map_err(DebuggerError::from)?;
+            Ok(Box::new(stream))
+        }
+        #[cfg(windows)]
+        DebuggerEndpoint::Pipe(name) => {
+            let client = tokio::net::windows::named_pipe::ClientOptions::new()
+                .open(name)
+                .map_err(DebuggerError::from)?;
+            Ok(Box::new(client))
+        }
+        _ => Err(unsupported(endpoint).into()),
+    }
+}
+
+/// Resolve `host:port` and connect to whichever address answers first. Attempts start
+/// one after the other with a short head start, alternating between ipv6 and ipv4 so a
+/// host that only listens on one family is still reached quickly.
+async fn connect_tcp(addr: &str, connect_timeout: Option<Duration>) -> DebuggerResult<TcpStream> {
+    let resolved = tokio::net::lookup_host(addr).await.map_err(|e| {
+        DebuggerError::ConnectionError(format!("could not resolve {}: {}", addr, e))
+    })?;
+    let mut candidates = interleave_families(resolved.collect())
+        .into_iter()
+        .peekable();
+    if candidates.peek().is_none() {
+        return Err(
+            DebuggerError::ConnectionError(format!("{} resolved to no address", addr)).into(),
+        );
+    }
+
+    let mut attempts = JoinSet::new();
+    let mut failures = vec![];
+    loop {
+        if let Some(candidate) = candidates.next() {
+            attempts.spawn(async move {
+                let connecting = TcpStream::connect(candidate);
+                let result = match connect_timeout {
+                    Some(connect_timeout) => timeout(connect_timeout, connecting)
+                        .await
+                        .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into())),
+                    None => connecting.await,
+                };
+                (candidate, result)
+            });
+        } else if attempts.is_empty() {
+            break;
+        }
+
+        tokio::select! {
+            Some(joined) = attempts.join_next() => match joined {
+                // dropping the join set aborts the attempts still running
+                Ok((_, Ok(stream))) => return Ok(stream),
+                Ok((candidate, Err(e))) => failures.push(format!("{} ({})", candidate, e)),
+                Err(e) => log::error!("Connection attempt failed to run: {}", e),
+            },
+            _ = tokio::time::sleep(CONNECT_ATTEMPT_DELAY), if candidates.peek().is_some() => {}
+            else => {}
+        }
+    }
+
+    Err(DebuggerError::ConnectionError(format!(
+        "could not connect to {}, tried {}",
+        addr,
+        failures.join(", ")
+    ))
+    .into())
+}
+
+/// Alternate address families, keeping the resolver's order within each family.
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let first_is_ipv6 = first.is_ipv6();
+    let (preferred, others): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_is_ipv6);
+    let mut others = others.into_iter();
+    let mut interleaved = vec![];
+    for addr in preferred {
+        interleaved.push(addr);
+        interleaved.extend(others.next());
+    }
+    interleaved.extend(others);
+    interleaved
+}
+
+/// An inherited descriptor must be an open socket, and never stdin, stdout or stderr
+/// which carry the editor's messages.
+#[cfg(unix)]
+fn check_inherited_socket(fd: i32) -> Result<(), DebuggerError> {
+    if fd <= 2 {
+        return Err(DebuggerError::ConnectionError(format!(
+            "fd {} is a standard stream, not a socket",
+            fd
+        )));
+    }
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: fstat only writes the stat buffer, an invalid descriptor fails with EBADF
+    if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } != 0 {
+        return Err(DebuggerError::ConnectionError(format!(
+            "fd {} is not open: {}",
+            fd,
+            std::io::Error::last_os_error()
+        )));
+    }
+    // SAFETY: fstat succeeded and filled the buffer
+    let mode = unsafe { stat.assume_init() }.st_mode;
+    if mode & libc::S_IFMT != libc::S_IFSOCK {
+        return Err(DebuggerError::ConnectionError(format!(
+            "fd {} is not a socket",
+            fd
+        )));
+    }
+    Ok(())
+}
+
+fn unsupported(endpoint: &DebuggerEndpoint) -> DebuggerError {
+    DebuggerError::ConnectionError(format!("{} is not supported on this platform", endpoint))
+}
+
+/// Accepts debuggees connecting to the adapter, with an acceptor every link is
+/// encrypted. Peers the guard turns away are dropped and reported to `on_reject`.
+pub struct DebuggerListener {
+    kind: ListenerKind,
+    tls: Option<TlsAcceptor>,
+    guard: PeerGuard,
+    on_reject: Option<Arc<dyn Fn(String) + Send + Sync>>,
+    /// handshakes of accepted peers, each runs on its own so a slow peer holds up nobody
+    handshakes: tokio::sync::Mutex<JoinSet<(String, Result<Link, String>)>>,
+}
+
+enum ListenerKind {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, String),
+    #[cfg(windows)]
+    Pipe(PipeListener),
+}
+
+impl DebuggerListener {
+    pub async fn bind(
+        endpoint: &DebuggerEndpoint,
+        tls: Option<TlsAcceptor>,
+    ) -> DebuggerResult<Self> {
+        let kind = match endpoint {
+            DebuggerEndpoint::Tcp(addr) => {
+                let listener = TcpListener::bind(addr.as_str())
+                    .await
+                    .map_err(DebuggerError::from)?;
+                ListenerKind::Tcp(listener)
+            }
+            #[cfg(unix)]
+            DebuggerEndpoint::Unix(path) => {
+                use std::os::unix::fs::FileTypeExt;
+                // a socket left behind by an earlier session would block the bind, one
+                // that still answers belongs to a live session
+                if std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                    match std::os::unix::net::UnixStream::connect(path) {
+                        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+                            let _ = std::fs::remove_file(path);
+                        }
+                        _ => {
+                            return Err(DebuggerError::ConnectionError(format!(
+                                "address in use: {} is served by another session",
+                                path
+                            ))
+                            .into());
+                        }
+                    }
+                }
+                let listener = tokio::net::UnixListener::bind(path).map_err(DebuggerError::from)?;
+                ListenerKind::Unix(listener, path.clone())
+            }
+            #[cfg(windows)]
+            DebuggerEndpoint::Pipe(name) => ListenerKind::Pipe(PipeListener::bind(name)?),
+            DebuggerEndpoint::Fd(_) => {
+                return Err(DebuggerError::ConnectionError(
+                    "an inherited descriptor is already connected, it can not be listened on"
+                        .to_string(),
+                )
+                .into());
+            }
+            _ => return Err(unsupported(endpoint).into()),
+        };
+        Ok(DebuggerListener {
+            kind,
+            tls,
+            guard: PeerGuard::default(),
+            on_reject: None,
+            handshakes: tokio::sync::Mutex::new(JoinSet::new()),
+        })
+    }
+
+    pub fn set_guard(&mut self, guard: PeerGuard) {
+        self.guard = guard;
+    }
+
+    pub fn on_reject(&mut self, on_reject: impl Fn(String) + Send + Sync + 'static) {
+        self.on_reject = Some(Arc::new(on_reject));
+    }
+
+    /// Wait for the next debuggee, returns its link and a description of the peer. Peers
+    /// keep being accepted while earlier ones are still in their handshake.
+    pub async fn accept(&self) -> DebuggerResult<(LinkReader, LinkWriter, String)> {
+        let mut handshakes = self.handshakes.lock().await;
+        loop {
+            tokio::select! {
+                accepted = self.accept_link() => {
+                    let (link, peer, peer_ip) = accepted?;
+                    let guard = self.guard.clone();
+                    let tls = self.tls.clone();
+                    handshakes.spawn(async move {
+                        let admitted = admit(&guard, tls.as_ref(), link, peer_ip).await;
+                        (peer, admitted)
+                    });
+                }
+                Some(joined) = handshakes.join_next() => match joined {
+                    Ok((peer, Ok(link))) => {
+                        let (read_stream, write_stream) = split_link(link);
+                        return Ok((read_stream, write_stream, peer));
+                    }
+                    // a peer failing the checks must not take the listener down
+                    Ok((peer, Err(reason))) => {
+                        log::warn!("Rejected connection from {}: {}", peer, reason);
+                        if let Some(on_reject) = &self.on_reject {
+                            on_reject(format!("Rejected connection from {}: {}", peer, reason));
+                        }
+                    }
+                    Err(e) => log::error!("Handshake failed to run: {}", e),
+                },
+            }
+        }
+    }
+
+    async fn accept_link(&self) -> DebuggerResult<(Link, String, Option<IpAddr>)> {
+        match &self.kind {
+            ListenerKind::Tcp(listener) => {
+                let (stream, peer_addr) = listener.accept().await.map_err(DebuggerError::from)?;
+                Ok((
+                    Box::new(stream),
+                    peer_addr.to_string(),
+                    Some(peer_addr.ip()),
+                ))
+            }
+            #[cfg(unix)]
+            ListenerKind::Unix(listener, path) => {
+                let (stream, _) = listener.accept().await.map_err(DebuggerError::from)?;
+                Ok((Box::new(stream), format!("unix:{}", path), None))
+            }
+            #[cfg(windows)]
+            ListenerKind::Pipe(listener) => {
+                let (server, name) = listener.accept().await?;
+                Ok((server, name, None))
+            }
+        }
+    }
+}
+
+/// Check a freshly accepted peer against `guard` and run its handshakes.
+async fn admit(
+    guard: &PeerGuard,
+    tls: Option<&TlsAcceptor>,
+    link: Link,
+    peer_ip: Option<IpAddr>,
+) -> Result<Link, String> {
+    if !guard.allows(peer_ip) {
+        return Err("address not in allowedPeers".to_string());
+    }
+    let mut link = match tls {
+        Some(acceptor) => match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(link)).await {
+            Ok(Ok(stream)) => Box::new(stream),
+            Ok(Err(e)) => return Err(format!("TLS handshake failed: {}", e)),
+            Err(_) => return Err("TLS handshake timed out".to_string()),
+        },
+        None => link,
+    };
+    if let Some(secret) = guard.secret() {
+        match timeout(HANDSHAKE_TIMEOUT, auth::challenge(&mut link, secret)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Err(format!("authentication failed: {}", e)),
+            Err(_) => return Err("authentication timed out".to_string()),
+        }
+    }
+    Ok(link)
+}
+
+#[cfg(unix)]
+impl Drop for DebuggerListener {
+    fn drop(&mut self) {
+        if let ListenerKind::Unix(_, path) = &self.kind {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// A named pipe serves one client per instance, the next instance is created as soon as
+/// one is taken so connecting debuggees always find the pipe.
+#[cfg(windows)]
+pub struct PipeListener {
+    name: String,
+    /// the instance waiting for a client, kept when a wait for it is cancelled
+    next: tokio::sync::Mutex<Option<tokio::net::windows::named_pipe::NamedPipeServer>>,
+}
+
+#[cfg(windows)]
+impl PipeListener {
+    fn bind(name: &str) -> DebuggerResult<Self> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+        let server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(name)
+            .map_err(DebuggerError::from)?;
+        Ok(PipeListener {
+            name: name.to_string(),
+            next: tokio::sync::Mutex::new(Some(server)),
+        })
+    }
+
+    async fn accept(&self) -> DebuggerResult<(Link, String)> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+        let mut next = self.next.lock().await;
+        let waiting = match next.take() {
+            Some(server) => server,
+            None => ServerOptions::new()
+                .create(&self.name)
+                .map_err(DebuggerError::from)?,
+        };
+        // the instance is only taken once a client is connected, an accept cancelled
+        // while waiting leaves it for the next one
+        let server = &*next.insert(waiting);
+        server.connect().await.map_err(DebuggerError::from)?;
+        let server = next.take().expect("pipe instance kept while connecting");
+        *next = ServerOptions::new().create(&self.name).ok();
+        Ok((Box::new(server), self.name.clone()))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::fd::AsRawFd;
+
+    use super::*;
+
+    #[test]
+    fn rejects_descriptors_that_are_no_socket() {
+        assert!(check_inherited_socket(1).is_err());
+        assert!(check_inherited_socket(-1).is_err());
+        let file = std::fs::File::open("Cargo.toml").unwrap();
+        assert!(check_inherited_socket(file.as_raw_fd()).is_err());
+        let (socket, _peer) = std::os::unix::net::UnixStream::pair().unwrap();
+        assert!(check_inherited_socket(socket.as_raw_fd()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn binds_over_stale_sockets_only() {
+        let path = std::env::temp_dir().join(format!("emmylua_dap_{}.sock", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let _ = std::fs::remove_file(&path);
+        let endpoint = DebuggerEndpoint::Unix(path.clone());
+
+        let live = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        assert!(DebuggerListener::bind(&endpoint, None).await.is_err());
+        drop(live);
+        // the socket file outlives its listener
+        assert!(std::fs::metadata(&path).is_ok());
+        assert!(DebuggerListener::bind(&endpoint, None).await.is_ok());
+    }
+}
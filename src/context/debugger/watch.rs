@@ -0,0 +1,32 @@
+/// Last rendered values of a watch expression, compared across stops.
+#[derive(Debug, Clone)]
+pub struct WatchValue {
+    /// stop the value was rendered at
+    stop_seq: u64,
+    value: String,
+    /// value at the stop before, watches are evaluated several times per stop
+    previous: Option<String>,
+}
+
+impl WatchValue {
+    pub fn new(stop_seq: u64, value: String) -> Self {
+        WatchValue {
+            stop_seq,
+            value,
+            previous: None,
+        }
+    }
+
+    /// Record the value rendered at `stop_seq`, returns the old value when it differs
+    /// from the one shown at the previous stop.
+    pub fn update(&mut self, stop_seq: u64, value: String) -> Option<String> {
+        if self.stop_seq != stop_seq {
+            self.previous = Some(std::mem::take(&mut self.value));
+            self.stop_seq = stop_seq;
+        }
+        self.value = value;
+        self.previous
+            .clone()
+            .filter(|previous| *previous != self.value)
+    }
+}
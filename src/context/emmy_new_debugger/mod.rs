@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+use crate::context::{
+    CONNECT_ATTEMPT_TIMEOUT, DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_MAX_STRING_LENGTH,
+    DEFAULT_REQUEST_TIMEOUT,
+};
+
 /// accpet number as integer
 pub mod port_deserializer {
     use serde::{Deserializer, de};
@@ -59,10 +64,164 @@ pub mod port_deserializer {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EmmyNewDebugArguments {
+    #[serde(default)]
     pub host: String,
-    #[serde(deserialize_with = "port_deserializer::deserialize")]
+    #[serde(default, deserialize_with = "port_deserializer::deserialize")]
     pub port: u16,
+    /// how the debuggee is reached, `host` and `port` only apply to tcp
+    #[serde(default)]
+    pub transport: TransportKind,
+    /// unix socket path or pipe name
+    #[serde(default)]
+    pub path: Option<String>,
+    /// connected socket inherited from the process that started the adapter
+    #[serde(default)]
+    pub fd: Option<i32>,
     pub ext: Vec<String>,
     pub ide_connect_debugger: bool,
     pub source_paths: Vec<String>,
+    /// strings longer than this are truncated in variables view
+    #[serde(default = "default_max_string_length")]
+    pub max_string_length: usize,
+    /// show coroutines created after the first stop as threads
+    #[serde(default)]
+    pub track_coroutines: bool,
+    /// chunk name globs of library code, runs of their frames are collapsed in the call stack
+    #[serde(default)]
+    pub library_files: Vec<String>,
+    /// chunk name globs stepping never stops in, steps continue until user code is reached
+    #[serde(default)]
+    pub skip_files: Vec<String>,
+    /// more lua states to connect to, each one is debugged as its own target
+    #[serde(default)]
+    pub targets: Vec<DebugEndpoint>,
+    /// listen mode keeps accepting connections, every one becomes a new target
+    #[serde(default)]
+    pub multiple_connections: bool,
+    /// record the last stops so stepBack and reverseContinue can replay them
+    #[serde(default)]
+    pub history: Option<HistoryOptions>,
+    /// keep the session when the debuggee drops the connection and link up again
+    #[serde(default)]
+    pub reconnect: ReconnectOptions,
+    /// how long to keep trying to reach a listening debuggee, in milliseconds
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+    /// pause between two connect attempts, in milliseconds
+    #[serde(default = "default_connect_retry_interval")]
+    pub connect_retry_interval: u64,
+    /// encrypt the debug channel
+    #[serde(default)]
+    pub tls: Option<TlsOptions>,
+    /// checks on debuggees connecting in listen mode
+    #[serde(default)]
+    pub auth: AuthOptions,
+    /// how long a request to the debuggee may wait for its response
+    #[serde(default = "default_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+    /// larger messages from the debuggee are dropped, in bytes
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryOptions {
+    /// how many stops are kept
+    #[serde(default = "default_history_size")]
+    pub size: usize,
+    /// depth of the table children recorded for every local
+    #[serde(default = "default_history_depth")]
+    pub depth: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    Unix,
+    Fd,
+    Pipe,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AuthOptions {
+    /// peers must answer a challenge keyed by this secret before they are debugged
+    pub secret: Option<String>,
+    /// addresses or networks (`10.0.0.0/8`) allowed to connect, empty allows all
+    pub allowed_peers: Vec<String>,
+}
+
+/// Paths of pem files for the encrypted debug channel.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TlsOptions {
+    /// certificate the adapter presents, required when it listens
+    pub cert: Option<String>,
+    pub key: Option<String>,
+    /// authority the peer's certificate is checked against
+    pub ca: Option<String>,
+    /// take any certificate from the debuggee, the link is encrypted but not authenticated
+    pub insecure_skip_verify: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReconnectOptions {
+    pub enabled: bool,
+    /// pause between two attempts
+    pub interval_ms: u64,
+    /// attempts before the session ends, 0 retries until the session is stopped
+    pub max_attempts: usize,
+}
+
+impl Default for ReconnectOptions {
+    fn default() -> Self {
+        ReconnectOptions {
+            enabled: false,
+            interval_ms: 1000,
+            max_attempts: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugEndpoint {
+    pub host: String,
+    #[serde(deserialize_with = "port_deserializer::deserialize")]
+    pub port: u16,
+    /// shown in front of the thread names, defaults to `host:port`
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+fn default_max_string_length() -> usize {
+    DEFAULT_MAX_STRING_LENGTH
+}
+
+fn default_request_timeout_ms() -> u64 {
+    DEFAULT_REQUEST_TIMEOUT.as_millis() as u64
+}
+
+fn default_max_message_size() -> usize {
+    DEFAULT_MAX_MESSAGE_SIZE
+}
+
+fn default_connect_timeout() -> u64 {
+    CONNECT_ATTEMPT_TIMEOUT.as_millis() as u64
+}
+
+fn default_connect_retry_interval() -> u64 {
+    500
+}
+
+fn default_history_size() -> usize {
+    50
+}
+
+fn default_history_depth() -> i64 {
+    2
 }
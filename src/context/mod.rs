@@ -1,41 +1,67 @@
+mod client;
 mod debugger;
 mod emmy_attach_debugger;
 mod emmy_new_debugger;
+mod progress;
 mod snapshot;
+mod target;
 
-use std::{collections::HashMap, future::Future, io::Stdout, sync::Arc};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{Stdout, Write},
+    sync::Arc,
+};
 
-use dap::{requests::Request, responses::Response, server::ServerOutput};
+use dap::{errors::ServerError, requests::Request, responses::Response, server::ServerOutput};
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
 use crate::handler::RequestResult;
+pub use client::ClientOptions;
 pub use debugger::*;
 pub use emmy_new_debugger::*;
+pub use progress::ProgressRegistry;
 pub use snapshot::DapSnapShot;
+pub use target::{to_target_frame_id, to_target_thread_id};
 
 pub struct EmmyLuaDebugContext {
-    debugger_conn: Arc<Mutex<debugger::DebuggerConnection>>,
+    targets: Arc<std::sync::Mutex<target::DebugTargets>>,
     cancellations: Arc<Mutex<HashMap<i64, CancellationToken>>>,
+    progress: Arc<std::sync::Mutex<ProgressRegistry>>,
+    client: Arc<std::sync::Mutex<ClientOptions>>,
     ide_conn: Arc<std::sync::Mutex<ServerOutput<Stdout>>>,
-    data: Arc<Mutex<DebuggerData>>,
 }
 
 impl EmmyLuaDebugContext {
     pub fn new(ide_conn: Arc<std::sync::Mutex<ServerOutput<Stdout>>>) -> Self {
+        let mut targets = target::DebugTargets::default();
+        targets.add(
+            String::new(),
+            debugger::DebuggerConnection::new(),
+            DebuggerData::default(),
+        );
         EmmyLuaDebugContext {
-            debugger_conn: Arc::new(Mutex::new(debugger::DebuggerConnection::new())),
+            targets: Arc::new(std::sync::Mutex::new(targets)),
             cancellations: Arc::new(Mutex::new(HashMap::new())),
+            progress: Arc::new(std::sync::Mutex::new(ProgressRegistry::default())),
+            client: Arc::new(std::sync::Mutex::new(ClientOptions::default())),
             ide_conn,
-            data: Arc::new(Mutex::new(DebuggerData::default())),
         }
     }
 
+    /// Requests start on the first target, handlers route by the ids they carry.
     fn snapshot(&self) -> DapSnapShot {
+        let target = self.targets.lock().unwrap().get(0).cloned().unwrap();
         DapSnapShot {
-            debugger_conn: self.debugger_conn.clone(),
+            debugger_conn: target.debugger_conn,
             ide_conn: self.ide_conn.clone(),
-            data: self.data.clone(),
+            data: target.data,
+            target_id: 0,
+            targets: self.targets.clone(),
+            progress: self.progress.clone(),
+            client: self.client.clone(),
+            after_response: Arc::default(),
         }
     }
 
@@ -44,6 +70,36 @@ impl EmmyLuaDebugContext {
         F: FnOnce(DapSnapShot, P, CancellationToken) -> Fut + Send + 'static,
         Fut: Future<Output = RequestResult> + Send + 'static,
         P: Send + 'static,
+    {
+        self.spawn_task(request, None, param, exec).await;
+    }
+
+    /// Like `task`, for requests the types crate has no response for. The handler answers
+    /// with a body of the same shape and the response goes out under `command`.
+    pub async fn task_as<F, P, Fut>(
+        &self,
+        request: Request,
+        command: &'static str,
+        param: P,
+        exec: F,
+    ) where
+        F: FnOnce(DapSnapShot, P, CancellationToken) -> Fut + Send + 'static,
+        Fut: Future<Output = RequestResult> + Send + 'static,
+        P: Send + 'static,
+    {
+        self.spawn_task(request, Some(command), param, exec).await;
+    }
+
+    async fn spawn_task<F, P, Fut>(
+        &self,
+        request: Request,
+        command: Option<&'static str>,
+        param: P,
+        exec: F,
+    ) where
+        F: FnOnce(DapSnapShot, P, CancellationToken) -> Fut + Send + 'static,
+        Fut: Future<Output = RequestResult> + Send + 'static,
+        P: Send + 'static,
     {
         let cancel_token = CancellationToken::new();
         let req_id = request.seq;
@@ -55,6 +111,7 @@ impl EmmyLuaDebugContext {
         let cancellations = self.cancellations.clone();
         let output = self.ide_conn.clone();
         let snapshot = self.snapshot();
+        let after_response = snapshot.after_response.clone();
         tokio::spawn(async move {
             let res = exec(snapshot, param, cancel_token.clone()).await;
             let response = if cancel_token.is_cancelled() {
@@ -68,7 +125,12 @@ impl EmmyLuaDebugContext {
             };
 
             if let Some(response) = response {
-                match output.lock().unwrap().respond(response) {
+                let success = response.success;
+                let result = match command {
+                    Some(command) if response.success => respond_as(&output, response, command),
+                    _ => output.lock().unwrap().respond(response),
+                };
+                match result {
                     Err(server_err) => {
                         log::error!(
                             "Failed to send response for request {}: {:?}",
@@ -80,6 +142,15 @@ impl EmmyLuaDebugContext {
                         log::debug!("Successfully sent response for request {}", req_id);
                     }
                 }
+                let events = std::mem::take(&mut *after_response.lock().unwrap());
+                if success && !events.is_empty() {
+                    let mut output = output.lock().unwrap();
+                    for event in events {
+                        if let Err(server_err) = output.send_event(event) {
+                            log::error!("Failed to send event after {}: {:?}", req_id, server_err);
+                        }
+                    }
+                }
             } else {
                 log::warn!("No response generated for request {}", req_id);
             }
@@ -96,6 +167,10 @@ impl EmmyLuaDebugContext {
         }
     }
 
+    pub fn cancel_progress(&self, progress_id: &str) {
+        self.progress.lock().unwrap().cancel(progress_id);
+    }
+
     pub async fn respond(&self, response: Response) {
         let mut output = self.ide_conn.lock().unwrap();
         let result = output.respond(response);
@@ -108,3 +183,68 @@ impl EmmyLuaDebugContext {
         }
     }
 }
+
+/// Send a response under another command name. Holding the output lock keeps it apart
+/// from the messages of `ServerOutput`, which flushes each of them. Its seq counter is
+/// private, the response carries seq 0, clients match responses by `request_seq`.
+fn respond_as(
+    output: &std::sync::Mutex<ServerOutput<Stdout>>,
+    response: Response,
+    command: &str,
+) -> Result<(), ServerError> {
+    let _output = output.lock().unwrap();
+    let json = response_json(response, command).map_err(ServerError::SerializationError)?;
+    let mut stdout = std::io::stdout().lock();
+    write!(stdout, "Content-Length: {}\r\n\r\n{}", json.len(), json)
+        .and_then(|_| stdout.flush())
+        .map_err(ServerError::IoError)
+}
+
+fn response_json(response: Response, command: &str) -> Result<String, serde_json::Error> {
+    let mut message = serde_json::to_value(response)?;
+    if let Some(fields) = message.as_object_mut() {
+        fields.insert("seq".to_string(), 0.into());
+        fields.insert("type".to_string(), "response".into());
+        fields.insert("command".to_string(), command.into());
+    }
+    serde_json::to_string(&message)
+}
+
+#[cfg(test)]
+mod tests {
+    use dap::{
+        responses::{GotoTargetsResponse, ResponseBody},
+        types::GotoTarget,
+    };
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    #[test]
+    fn renames_the_response_command() {
+        let response = Response {
+            request_seq: 7,
+            success: true,
+            message: None,
+            body: Some(ResponseBody::GotoTargets(GotoTargetsResponse {
+                targets: vec![GotoTarget {
+                    id: 1,
+                    label: "f".to_string(),
+                    line: 3,
+                    column: Some(5),
+                    ..Default::default()
+                }],
+            })),
+            error: None,
+        };
+        let json = response_json(response, "stepInTargets").unwrap();
+        let message = serde_json::from_str::<Value>(&json).unwrap();
+        assert_eq!(message["type"], "response");
+        assert_eq!(message["command"], "stepInTargets");
+        assert_eq!(message["request_seq"], 7);
+        assert_eq!(
+            message["body"],
+            json!({"targets": [{"id": 1, "label": "f", "line": 3, "column": 5}]})
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+use tokio_util::sync::CancellationToken;
+
+/// Long running operations reported to the editor, the `cancel` request stops them by
+/// their progress id.
+#[derive(Debug, Default)]
+pub struct ProgressRegistry {
+    /// the editor asked for progress events in its initialize request
+    pub enabled: bool,
+    next_id: u64,
+    running: HashMap<String, CancellationToken>,
+}
+
+impl ProgressRegistry {
+    /// Register an operation, returns its progress id.
+    pub fn start(&mut self, cancel: CancellationToken) -> String {
+        self.next_id += 1;
+        let progress_id = format!("emmylua-{}", self.next_id);
+        self.running.insert(progress_id.clone(), cancel);
+        progress_id
+    }
+
+    pub fn end(&mut self, progress_id: &str) {
+        self.running.remove(progress_id);
+    }
+
+    pub fn cancel(&self, progress_id: &str) {
+        if let Some(cancel) = self.running.get(progress_id) {
+            cancel.cancel();
+        }
+    }
+}
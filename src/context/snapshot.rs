@@ -1,13 +1,139 @@
 use std::{io::Stdout, sync::Arc};
 
-use dap::server::ServerOutput;
+use dap::{events::Event, server::ServerOutput};
 use tokio::sync::Mutex;
 
-use super::{DebuggerData, debugger::DebuggerConnection};
+use super::{
+    ClientOptions, DebuggerData, ProgressRegistry,
+    debugger::DebuggerConnection,
+    target::{
+        DebugTarget, DebugTargets, split_target_frame_id, split_target_thread_id,
+        variable_target_id,
+    },
+};
 
+/// View of one debug target, `targets` gives access to the other lua states of the session.
 #[derive(Clone)]
 pub struct DapSnapShot {
     pub debugger_conn: Arc<Mutex<DebuggerConnection>>,
     pub ide_conn: Arc<std::sync::Mutex<ServerOutput<Stdout>>>,
     pub data: Arc<Mutex<DebuggerData>>,
+    pub target_id: usize,
+    pub targets: Arc<std::sync::Mutex<DebugTargets>>,
+    pub progress: Arc<std::sync::Mutex<ProgressRegistry>>,
+    pub client: Arc<std::sync::Mutex<ClientOptions>>,
+    /// events of the request this view was made for, sent once it was answered
+    pub after_response: Arc<std::sync::Mutex<Vec<Event>>>,
+}
+
+impl DapSnapShot {
+    fn with_target(&self, target_id: usize, target: &DebugTarget) -> DapSnapShot {
+        DapSnapShot {
+            debugger_conn: target.debugger_conn.clone(),
+            ide_conn: self.ide_conn.clone(),
+            data: target.data.clone(),
+            target_id,
+            targets: self.targets.clone(),
+            progress: self.progress.clone(),
+            client: self.client.clone(),
+            after_response: self.after_response.clone(),
+        }
+    }
+
+    pub fn target(&self, target_id: usize) -> Option<DapSnapShot> {
+        let targets = self.targets.lock().unwrap();
+        targets
+            .get(target_id)
+            .map(|target| self.with_target(target_id, target))
+    }
+
+    pub fn all_targets(&self) -> Vec<DapSnapShot> {
+        let targets = self.targets.lock().unwrap();
+        targets
+            .iter()
+            .map(|(target_id, target)| self.with_target(target_id, target))
+            .collect()
+    }
+
+    /// Send an event right after the response of the request being handled, for events
+    /// the editor must see after the response. Only works inside request handlers.
+    pub fn send_after_response(&self, event: Event) {
+        self.after_response.lock().unwrap().push(event);
+    }
+
+    pub fn target_count(&self) -> usize {
+        self.targets.lock().unwrap().count()
+    }
+
+    pub fn target_name(&self) -> String {
+        let targets = self.targets.lock().unwrap();
+        targets
+            .get(self.target_id)
+            .map(|target| target.name.clone())
+            .unwrap_or_default()
+    }
+
+    /// Target of the last stop.
+    pub fn focused_target(&self) -> DapSnapShot {
+        let focused = self.targets.lock().unwrap().focused;
+        self.target(focused).unwrap_or_else(|| self.clone())
+    }
+
+    pub fn set_focused(&self) {
+        self.targets.lock().unwrap().focused = self.target_id;
+    }
+
+    pub fn set_target_name(&self, name: String) {
+        self.targets.lock().unwrap().rename(self.target_id, name);
+    }
+
+    /// Add a connected target sharing the configuration of this one and return its view,
+    /// `None` when the session holds as many targets as ids can tell apart.
+    pub async fn add_target(
+        &self,
+        name: String,
+        debugger_conn: DebuggerConnection,
+    ) -> Option<DapSnapShot> {
+        let data = self.data.lock().await.clone_config();
+        let target_id = self
+            .targets
+            .lock()
+            .unwrap()
+            .add(name, debugger_conn, data)?;
+        self.target(target_id)
+    }
+
+    /// Drop this target from the session, the first target is never removed.
+    pub fn remove_target(&self) -> bool {
+        self.targets
+            .lock()
+            .unwrap()
+            .remove(self.target_id)
+            .is_some()
+    }
+
+    /// Resolve a dap thread id to its target and the thread id inside that target.
+    pub fn thread_target(&self, thread_id: i64) -> Option<(DapSnapShot, i64)> {
+        let (target_id, thread_id) = split_target_thread_id(thread_id);
+        self.target(target_id).map(|target| (target, thread_id))
+    }
+
+    /// Resolve a dap frame id, requests without a frame go to the focused target.
+    pub fn frame_target(&self, frame_id: Option<i64>) -> Option<(DapSnapShot, Option<i64>)> {
+        match frame_id {
+            Some(frame_id) => {
+                let (target_id, frame_id) = split_target_frame_id(frame_id);
+                if frame_id < 0 {
+                    return Some((self.focused_target(), Some(frame_id)));
+                }
+                self.target(target_id)
+                    .map(|target| (target, Some(frame_id)))
+            }
+            None => Some((self.focused_target(), None)),
+        }
+    }
+
+    pub fn variables_target(&self, variables_reference: i64) -> Option<DapSnapShot> {
+        self.target(variable_target_id(variables_reference))
+    }
 }
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use super::{DebuggerCache, DebuggerConnection, DebuggerData};
+
+/// Every id handed to the editor (thread, frame, variables reference) carries the index
+/// of the debug target it belongs to, target 0 keeps the plain ids.
+pub const TARGET_THREAD_STRIDE: i64 = 1_000_000;
+pub const TARGET_FRAME_STRIDE: i64 = 1 << 40;
+/// variables references must stay below 2^31
+pub const TARGET_VARIABLE_STRIDE: i64 = 10_000_000;
+/// targets that fit below 2^31 with their variables references
+pub const MAX_TARGETS: usize = (i32::MAX as i64 / TARGET_VARIABLE_STRIDE) as usize;
+
+/// One connected lua state.
+#[derive(Debug, Clone)]
+pub struct DebugTarget {
+    pub name: String,
+    pub debugger_conn: Arc<Mutex<DebuggerConnection>>,
+    pub data: Arc<Mutex<DebuggerData>>,
+}
+
+#[derive(Debug, Default)]
+pub struct DebugTargets {
+    /// slots of removed targets are reused, their ids stay unique while connected
+    targets: Vec<Option<DebugTarget>>,
+    /// target of the last stop, used for requests which carry no id
+    pub focused: usize,
+}
+
+impl DebugTargets {
+    pub fn get(&self, target_id: usize) -> Option<&DebugTarget> {
+        self.targets.get(target_id)?.as_ref()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &DebugTarget)> {
+        self.targets
+            .iter()
+            .enumerate()
+            .filter_map(|(target_id, target)| Some((target_id, target.as_ref()?)))
+    }
+
+    pub fn count(&self) -> usize {
+        self.targets.iter().flatten().count()
+    }
+
+    pub fn rename(&mut self, target_id: usize, name: String) {
+        if let Some(Some(target)) = self.targets.get_mut(target_id) {
+            target.name = name;
+        }
+    }
+
+    /// Add a target, its cache hands out variables references in its own range. `None`
+    /// when all ranges are taken.
+    pub fn add(
+        &mut self,
+        name: String,
+        debugger_conn: DebuggerConnection,
+        mut data: DebuggerData,
+    ) -> Option<usize> {
+        let target_id = match self.targets.iter().position(Option::is_none) {
+            Some(target_id) => target_id,
+            None if self.targets.len() < MAX_TARGETS => {
+                self.targets.push(None);
+                self.targets.len() - 1
+            }
+            None => return None,
+        };
+        let max_string_length = data.cache.max_string_length;
+        data.cache = DebuggerCache::with_range(
+            target_id as i64 * TARGET_VARIABLE_STRIDE + 1,
+            TARGET_VARIABLE_STRIDE - 1,
+        );
+        data.cache.max_string_length = max_string_length;
+        self.targets[target_id] = Some(DebugTarget {
+            name,
+            debugger_conn: Arc::new(Mutex::new(debugger_conn)),
+            data: Arc::new(Mutex::new(data)),
+        });
+        Some(target_id)
+    }
+
+    /// Forget a target whose debuggee went away, the first target stays for the session.
+    pub fn remove(&mut self, target_id: usize) -> Option<DebugTarget> {
+        if target_id == 0 {
+            return None;
+        }
+        let target = self.targets.get_mut(target_id)?.take();
+        if self.focused == target_id {
+            self.focused = 0;
+        }
+        target
+    }
+}
+
+pub fn to_target_thread_id(target_id: usize, thread_id: i64) -> i64 {
+    target_id as i64 * TARGET_THREAD_STRIDE + thread_id
+}
+
+pub fn split_target_thread_id(thread_id: i64) -> (usize, i64) {
+    (
+        (thread_id / TARGET_THREAD_STRIDE) as usize,
+        thread_id % TARGET_THREAD_STRIDE,
+    )
+}
+
+pub fn to_target_frame_id(target_id: usize, frame_id: i64) -> i64 {
+    target_id as i64 * TARGET_FRAME_STRIDE + frame_id
+}
+
+/// `-1` (no frame) stays on the given default target
+pub fn split_target_frame_id(frame_id: i64) -> (usize, i64) {
+    if frame_id < 0 {
+        return (0, frame_id);
+    }
+    (
+        (frame_id / TARGET_FRAME_STRIDE) as usize,
+        frame_id % TARGET_FRAME_STRIDE,
+    )
+}
+
+pub fn variable_target_id(variables_reference: i64) -> usize {
+    (variables_reference / TARGET_VARIABLE_STRIDE) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(targets: &mut DebugTargets, name: &str) -> Option<usize> {
+        targets.add(
+            name.to_string(),
+            DebuggerConnection::new(),
+            DebuggerData::default(),
+        )
+    }
+
+    #[test]
+    fn reuses_slots_of_removed_targets() {
+        let mut targets = DebugTargets::default();
+        assert_eq!(add(&mut targets, "main"), Some(0));
+        assert_eq!(add(&mut targets, "a"), Some(1));
+        assert_eq!(add(&mut targets, "b"), Some(2));
+        targets.focused = 1;
+
+        assert!(targets.remove(0).is_none());
+        assert!(targets.remove(1).is_some());
+        assert!(targets.remove(1).is_none());
+        assert_eq!(targets.focused, 0);
+        assert_eq!(targets.count(), 2);
+        assert_eq!(
+            targets.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+
+        assert_eq!(add(&mut targets, "c"), Some(1));
+        assert_eq!(targets.get(1).unwrap().name, "c");
+    }
+
+    #[test]
+    fn keeps_variables_references_below_i32() {
+        let mut targets = DebugTargets::default();
+        for _ in 0..MAX_TARGETS {
+            assert!(add(&mut targets, "t").is_some());
+        }
+        assert_eq!(add(&mut targets, "t"), None);
+        assert!((MAX_TARGETS as i64) * TARGET_VARIABLE_STRIDE <= i32::MAX as i64);
+        assert_eq!(variable_target_id(TARGET_VARIABLE_STRIDE * 3 + 5), 3);
+    }
+
+    #[test]
+    fn splits_ids() {
+        let thread_id = to_target_thread_id(3, 7);
+        assert_eq!(split_target_thread_id(thread_id), (3, 7));
+        let frame_id = to_target_frame_id(2, 100_005);
+        assert_eq!(split_target_frame_id(frame_id), (2, 100_005));
+        assert_eq!(split_target_frame_id(-1), (0, -1));
+    }
+}
@@ -0,0 +1,221 @@
+use dap::{
+    requests::CompletionsArguments,
+    responses::{CompletionsResponse, ResponseBody},
+    types::{CompletionItem, CompletionItemType},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    context::{DapSnapShot, format_string_value},
+    handler::{RequestHandlerError, step_back_request::replaying_error},
+    lua_syntax::{LUA_KEYWORDS, parse_completion_prefix},
+};
+
+use super::RequestResult;
+
+/// `name type` lines for the identifier keys of a value and of its `__index` chain that
+/// start with the prefix. Filtered in the debuggee and returned as one string, so a big
+/// `_G` is not sent over as variables.
+const KEYS_EXPR: &str = r#"(function(o, prefix)
+    local lines, seen, visited = {}, {}, {}
+    local function add(t)
+        for k, v in next, t do
+            if type(k) == "string" and not seen[k] and k:sub(1, #prefix) == prefix
+                and k:match("^[%a_][%w_]*$") then
+                seen[k] = true
+                lines[#lines + 1] = k .. " " .. type(v)
+            end
+        end
+    end
+    if type(o) == "table" then add(o) end
+    local mt = getmetatable(o)
+    while type(mt) == "table" do
+        local index = rawget(mt, "__index")
+        if type(index) ~= "table" or visited[index] then break end
+        visited[index] = true
+        add(index)
+        mt = getmetatable(index)
+    end
+    return table.concat(lines, "\n")
+end)"#;
+
+pub async fn on_completions_request(
+    dap: DapSnapShot,
+    completions_arguments: CompletionsArguments,
+    cancel: CancellationToken,
+) -> RequestResult {
+    log::debug!("Received Completions request: {:#?}", completions_arguments);
+    let client = *dap.client.lock().unwrap();
+    let line_base = client.lines_start_at1 as i64;
+    let column_base = client.columns_start_at1 as i64;
+    let line_index = completions_arguments
+        .line
+        .map(|line| (line - line_base).max(0) as usize)
+        .unwrap_or(0);
+    let line_text = completions_arguments
+        .text
+        .split('\n')
+        .nth(line_index)
+        .unwrap_or("");
+    let column = (completions_arguments.column - column_base).max(0) as usize;
+    let prefix_end = utf16_to_byte_offset(line_text, column);
+    let prefix = parse_completion_prefix(&line_text[..prefix_end]);
+
+    let Some((dap, frame_id)) = dap.frame_target(completions_arguments.frame_id) else {
+        return Err(RequestHandlerError::Message("Unknown stack frame".to_string()).into());
+    };
+    let (frame_id, frame_names) = {
+        let data = dap.data.lock().await;
+        if data.history.is_replaying() {
+            return Err(replaying_error());
+        }
+        let frame_id = frame_id.unwrap_or(data.current_frame_id);
+        let frame_names = match data.get_stack(frame_id) {
+            Some(stack) => stack
+                .local_variables
+                .iter()
+                .chain(stack.upvalue_variables.iter())
+                .map(|var| var.name.clone())
+                .collect::<Vec<_>>(),
+            None => vec![],
+        };
+        (frame_id, frame_names)
+    };
+
+    let mut candidates: Vec<(String, CompletionItemType)> = vec![];
+    if let Some(receiver) = &prefix.receiver {
+        for (name, type_name) in eval_keys(&dap, receiver, &prefix.partial, frame_id, &cancel).await
+        {
+            candidates.push((name, member_type(type_name == "function", prefix.is_method)));
+        }
+
+        if prefix.is_method {
+            candidates.retain(|(_, kind)| matches!(kind, CompletionItemType::Method));
+        }
+    } else {
+        for name in frame_names {
+            candidates.push((name, CompletionItemType::Variable));
+        }
+        for (name, type_name) in eval_keys(&dap, "_G", &prefix.partial, frame_id, &cancel).await {
+            let kind = match type_name.as_str() {
+                "function" => CompletionItemType::Function,
+                "table" => CompletionItemType::Module,
+                _ => CompletionItemType::Variable,
+            };
+            candidates.push((name, kind));
+        }
+        for keyword in LUA_KEYWORDS {
+            candidates.push((keyword.to_string(), CompletionItemType::Keyword));
+        }
+    }
+
+    // positions are counted in utf16 code units
+    let start = utf16_len(&line_text[..prefix.partial_start]) + column_base;
+    let length = utf16_len(&prefix.partial);
+    let mut targets: Vec<CompletionItem> = vec![];
+    for (label, kind) in candidates {
+        if !label.starts_with(&prefix.partial) || targets.iter().any(|t| t.label == label) {
+            continue;
+        }
+        targets.push(CompletionItem {
+            label,
+            type_field: Some(kind),
+            start: Some(start),
+            length: Some(length),
+            ..Default::default()
+        });
+    }
+
+    Ok(ResponseBody::Completions(CompletionsResponse { targets }))
+}
+
+fn member_type(is_function: bool, is_method: bool) -> CompletionItemType {
+    match (is_function, is_method) {
+        (true, true) => CompletionItemType::Method,
+        (true, false) => CompletionItemType::Function,
+        _ => CompletionItemType::Field,
+    }
+}
+
+/// Names and type names of the keys of `expression` starting with `partial`, failures
+/// only mean no completion.
+async fn eval_keys(
+    dap: &DapSnapShot,
+    expression: &str,
+    partial: &str,
+    frame_id: i64,
+    cancel: &CancellationToken,
+) -> Vec<(String, String)> {
+    let expression = format!(
+        "{}({}, {})",
+        KEYS_EXPR,
+        expression,
+        format_string_value(partial, None)
+    );
+    let requests = dap.debugger_conn.lock().await.requests();
+    match requests.eval_expr(expression, 0, 1, frame_id, cancel).await {
+        Ok(eval_rsp) if eval_rsp.success => eval_rsp
+            .value
+            .map(|value| parse_keys(&value.value))
+            .unwrap_or_default(),
+        Ok(eval_rsp) => {
+            log::debug!("Completion eval failed: {:?}", eval_rsp.error);
+            vec![]
+        }
+        Err(err) => {
+            log::error!("Error evaluating completion: {}", err);
+            vec![]
+        }
+    }
+}
+
+fn parse_keys(keys: &str) -> Vec<(String, String)> {
+    keys.lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, type_name)| (name.to_string(), type_name.to_string()))
+        .collect()
+}
+
+/// Byte offset of a position counted in utf16 code units, clamped to the line.
+fn utf16_to_byte_offset(line: &str, column: usize) -> usize {
+    let mut units = 0;
+    for (offset, c) in line.char_indices() {
+        if units >= column {
+            return offset;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+fn utf16_len(text: &str) -> i64 {
+    text.encode_utf16().count() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_utf16_columns() {
+        assert_eq!(utf16_to_byte_offset("abc", 0), 0);
+        assert_eq!(utf16_to_byte_offset("abc", 2), 2);
+        assert_eq!(utf16_to_byte_offset("abc", 9), 3);
+        // `中` is one utf16 unit and three bytes, `😀` two units and four bytes
+        assert_eq!(utf16_to_byte_offset("中a", 1), 3);
+        assert_eq!(utf16_to_byte_offset("😀a", 2), 4);
+        assert_eq!(utf16_len("😀中a"), 4);
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(
+            parse_keys("print function\nx number"),
+            vec![
+                ("print".to_string(), "function".to_string()),
+                ("x".to_string(), "number".to_string())
+            ]
+        );
+        assert!(parse_keys("").is_empty());
+    }
+}
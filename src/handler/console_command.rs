@@ -0,0 +1,164 @@
+use std::fmt::Write;
+
+use dap::responses::{EvaluateResponse, ResponseBody};
+
+use crate::{
+    cmd_args::LogLevel, context::DapSnapShot, handler::RequestHandlerError, logger::set_log_level,
+};
+
+use super::RequestResult;
+
+const HELP: &str = "\
+.bt           show the full traceback of the current stop
+.frame N      evaluate console input in frame N
+.bp           list active breakpoints
+.paths        show how chunk names were resolved to files
+.log LEVEL    change the adapter log level (error, warn, info, debug)
+.help         show this help";
+
+/// Console input starting with `.` followed by a command name is handled by the adapter,
+/// `.5` is still a lua number.
+pub fn is_console_command(input: &str) -> bool {
+    input
+        .trim_start()
+        .strip_prefix('.')
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| c.is_ascii_alphabetic())
+}
+
+pub async fn on_console_command(dap: DapSnapShot, input: &str) -> RequestResult {
+    let input = input.trim_start().trim_start_matches('.');
+    let mut parts = input.split_whitespace();
+    let command = parts.next().unwrap_or("");
+    let args = parts.collect::<Vec<_>>();
+
+    let output = match command {
+        "bt" => backtrace(&dap).await,
+        "frame" => switch_frame(&dap, &args).await?,
+        "bp" => breakpoints(&dap).await,
+        "paths" => resolved_paths(&dap).await,
+        "log" => change_log_level(&args)?,
+        "help" => HELP.to_string(),
+        _ => {
+            return Err(RequestHandlerError::Message(format!(
+                "Unknown command .{}, try .help",
+                command
+            ))
+            .into());
+        }
+    };
+
+    Ok(ResponseBody::Evaluate(EvaluateResponse {
+        result: output,
+        variables_reference: 0,
+        ..Default::default()
+    }))
+}
+
+async fn backtrace(dap: &DapSnapShot) -> String {
+    let data = dap.data.lock().await;
+    if !data.is_stopped() {
+        return "not stopped".to_string();
+    }
+
+    let mut output = String::new();
+    for stack in &data.stacks {
+        let marker = if stack.level as i64 == data.current_frame_id {
+            "*"
+        } else {
+            " "
+        };
+        let file = match data.file_cache.get(&stack.file) {
+            Some(Some(path)) => path.as_str(),
+            _ => stack.file.as_str(),
+        };
+        let _ = writeln!(
+            output,
+            "{}#{} {} {}:{}",
+            marker, stack.level, stack.function_name, file, stack.line
+        );
+    }
+    output.trim_end().to_string()
+}
+
+async fn switch_frame(dap: &DapSnapShot, args: &[&str]) -> Result<String, RequestHandlerError> {
+    let frame_id = args
+        .first()
+        .and_then(|arg| arg.parse::<i64>().ok())
+        .ok_or_else(|| RequestHandlerError::Message("usage: .frame N".to_string()))?;
+
+    let mut data = dap.data.lock().await;
+    let Some(stack) = data
+        .stacks
+        .iter()
+        .find(|stack| stack.level as i64 == frame_id)
+    else {
+        return Err(RequestHandlerError::Message(format!(
+            "no frame {}, the current stop has {} frames",
+            frame_id,
+            data.stacks.len()
+        )));
+    };
+    let output = format!(
+        "frame #{} {} {}:{}",
+        stack.level, stack.function_name, stack.file, stack.line
+    );
+    data.current_frame_id = frame_id;
+    Ok(output)
+}
+
+async fn breakpoints(dap: &DapSnapShot) -> String {
+    let data = dap.data.lock().await;
+    if data.breakpoints.is_empty() {
+        return "no breakpoints".to_string();
+    }
+
+    let mut breakpoints = data.breakpoints.values().collect::<Vec<_>>();
+    breakpoints.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    let mut output = String::new();
+    for breakpoint in breakpoints {
+        let _ = write!(output, "{}:{}", breakpoint.file, breakpoint.line);
+        if let Some(condition) = &breakpoint.condition {
+            let _ = write!(output, " if {}", condition);
+        }
+        if let Some(hit_condition) = &breakpoint.hit_condition {
+            let _ = write!(output, " hit {}", hit_condition);
+        }
+        if let Some(log_message) = &breakpoint.log_message {
+            let _ = write!(output, " log \"{}\"", log_message);
+        }
+        output.push('\n');
+    }
+    output.trim_end().to_string()
+}
+
+async fn resolved_paths(dap: &DapSnapShot) -> String {
+    let data = dap.data.lock().await;
+    if data.file_cache.is_empty() {
+        return "no chunk resolved yet".to_string();
+    }
+
+    let mut entries = data.file_cache.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    let mut output = String::new();
+    for (chunkname, path) in entries {
+        let _ = writeln!(
+            output,
+            "{} -> {}",
+            chunkname,
+            path.as_deref().unwrap_or("<not found>")
+        );
+    }
+    output.trim_end().to_string()
+}
+
+fn change_log_level(args: &[&str]) -> Result<String, RequestHandlerError> {
+    let level = args
+        .first()
+        .ok_or_else(|| RequestHandlerError::Message("usage: .log LEVEL".to_string()))?
+        .parse::<LogLevel>()
+        .map_err(RequestHandlerError::Message)?;
+    set_log_level(level);
+    let name = format!("{:?}", level).to_lowercase();
+    Ok(format!("log level set to {}", name))
+}
@@ -0,0 +1,184 @@
+use tokio_util::sync::CancellationToken;
+
+use crate::context::{DapSnapShot, LuaCoroutine, MAIN_THREAD_ID, Stack, ValueType, Variable};
+
+/// Wraps `coroutine.create`/`coroutine.wrap` on first use so new coroutines are
+/// registered in a weak table, every coroutine gets a stable id starting at 1. Returns
+/// the tracker id of the running coroutine, 0 for the main thread or untracked ones.
+const TRACK_COROUTINES_EXPR: &str = r#"(function()
+    local registry = rawget(_G, "__emmy_coroutines")
+    if not registry then
+        registry = setmetatable({}, {__mode = "k"})
+        local next_id = 0
+        local create, resume, getinfo, raise = coroutine.create, coroutine.resume, debug.getinfo, error
+        local function track(co, f)
+            next_id = next_id + 1
+            local info = getinfo(f, "S")
+            registry[co] = {id = next_id, src = info and (info.short_src .. ":" .. info.linedefined) or "?"}
+            return co
+        end
+        coroutine.create = function(f) return track(create(f), f) end
+        coroutine.wrap = function(f)
+            local co = track(create(f), f)
+            local function unpack_result(ok, ...)
+                if not ok then raise((...), 0) end
+                return ...
+            end
+            return function(...) return unpack_result(resume(co, ...)) end
+        end
+        rawset(_G, "__emmy_coroutines", registry)
+    end
+    local co, is_main = coroutine.running()
+    local entry = co and not is_main and registry[co]
+    return entry and entry.id or 0
+end)()"#;
+
+/// `id|status|source` for every live tracked coroutine
+const LIST_COROUTINES_EXPR: &str = r#"(function()
+    local list = {}
+    for co, entry in pairs(rawget(_G, "__emmy_coroutines") or {}) do
+        local status = coroutine.status(co)
+        if status ~= "dead" then
+            list[#list + 1] = entry.id .. "|" .. status .. "|" .. entry.src
+        end
+    end
+    return list
+end)()"#;
+
+/// frames of a suspended coroutine with their locals, `%d` is the tracker id
+const COROUTINE_STACK_EXPR: &str = r#"(function(id)
+    local target
+    for co, entry in pairs(rawget(_G, "__emmy_coroutines") or {}) do
+        if entry.id == id then target = co break end
+    end
+    local frames = {}
+    if not target then return frames end
+    local level = 0
+    while true do
+        local info = debug.getinfo(target, level, "Sln")
+        if not info then break end
+        local locals, i = {}, 1
+        while true do
+            local name, value = debug.getlocal(target, level, i)
+            if not name then break end
+            if name:sub(1, 1) ~= "(" then locals[name] = value end
+            i = i + 1
+        end
+        frames[#frames + 1] = {source = info.source, line = info.currentline or -1, name = info.name or "?", locals = locals}
+        level = level + 1
+    end
+    return frames
+end)(%d)"#;
+
+/// Make sure the tracker is installed and return the thread id of the coroutine that
+/// hit the break. A single eval on every stop, the coroutine list is fetched when the
+/// editor asks for threads.
+pub async fn refresh_coroutines(dap: &DapSnapShot) -> i64 {
+    if !dap.data.lock().await.track_coroutines {
+        return MAIN_THREAD_ID;
+    }
+
+    let Some(running) = eval(dap, TRACK_COROUTINES_EXPR.to_string(), 1).await else {
+        return MAIN_THREAD_ID;
+    };
+    let mut data = dap.data.lock().await;
+    data.coroutine_tracker_installed = true;
+    data.thread_stacks.clear();
+    match running.value.parse::<f64>() {
+        Ok(id) if id > 0.0 => data
+            .coroutine_ids
+            .thread_id(id as i64)
+            .unwrap_or(MAIN_THREAD_ID),
+        _ => MAIN_THREAD_ID,
+    }
+}
+
+pub async fn list_coroutines(dap: &DapSnapShot) -> Vec<LuaCoroutine> {
+    let Some(list) = eval(dap, LIST_COROUTINES_EXPR.to_string(), 2).await else {
+        return vec![];
+    };
+
+    let entries = list
+        .children
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|entry| {
+            let mut parts = entry.value.splitn(3, '|');
+            let tracker_id = parts.next()?.parse::<f64>().ok()? as i64;
+            let status = parts.next()?.to_string();
+            let source = parts.next().unwrap_or("?").to_string();
+            Some((tracker_id, status, source))
+        })
+        .collect::<Vec<_>>();
+
+    let mut data = dap.data.lock().await;
+    let live = entries.iter().map(|(tracker_id, ..)| *tracker_id).collect();
+    data.coroutine_ids.retain(&live);
+    let mut coroutines = entries
+        .into_iter()
+        .filter_map(|(tracker_id, status, source)| {
+            Some(LuaCoroutine {
+                thread_id: data.coroutine_ids.thread_id(tracker_id)?,
+                tracker_id,
+                status,
+                source,
+            })
+        })
+        .collect::<Vec<_>>();
+    coroutines.sort_by_key(|co| co.thread_id);
+    coroutines
+}
+
+/// Fetch the stack of a coroutine which is not the one that hit the break.
+pub async fn fetch_coroutine_stacks(dap: &DapSnapShot, thread_id: i64) -> Vec<Stack> {
+    let Some(tracker_id) = dap.data.lock().await.coroutine_ids.tracker_id(thread_id) else {
+        return vec![];
+    };
+    let expr = COROUTINE_STACK_EXPR.replace("%d", &tracker_id.to_string());
+    let Some(frames) = eval(dap, expr, 4).await else {
+        return vec![];
+    };
+
+    let mut frames = frames.children.unwrap_or_default();
+    frames.sort_by_key(|frame| frame.name.parse::<f64>().unwrap_or(0.0) as i64);
+    frames
+        .into_iter()
+        .enumerate()
+        .map(|(level, frame)| {
+            let fields = frame.children.unwrap_or_default();
+            let field = |name: &str| fields.iter().find(|field| field.name == name);
+            Stack {
+                file: field("source").map(|f| f.value.clone()).unwrap_or_default(),
+                line: field("line")
+                    .and_then(|f| f.value.parse::<f64>().ok())
+                    .unwrap_or(-1.0) as i32,
+                function_name: field("name").map(|f| f.value.clone()).unwrap_or_default(),
+                level: level as i32,
+                local_variables: field("locals")
+                    .and_then(|f| f.children.clone())
+                    .unwrap_or_default(),
+                upvalue_variables: vec![],
+            }
+        })
+        .collect()
+}
+
+async fn eval(dap: &DapSnapShot, expression: String, depth: i64) -> Option<Variable> {
+    let requests = dap.debugger_conn.lock().await.requests();
+    match requests
+        .eval_expr(expression, 0, depth, 0, &CancellationToken::new())
+        .await
+    {
+        Ok(eval_rsp) if eval_rsp.success => eval_rsp
+            .value
+            .filter(|value| value.value_type != ValueType::TNIL),
+        Ok(eval_rsp) => {
+            log::warn!("Coroutine tracker eval failed: {:?}", eval_rsp.error);
+            None
+        }
+        Err(err) => {
+            log::error!("Error evaluating coroutine tracker: {}", err);
+            None
+        }
+    }
+}
@@ -1,73 +1,138 @@
-use dap::responses::{ContinueResponse, ResponseBody};
+use dap::{
+    requests::{
+        ContinueArguments, NextArguments, PauseArguments, StepInArguments, StepOutArguments,
+    },
+    responses::{ContinueResponse, ResponseBody},
+};
 use tokio_util::sync::CancellationToken;
 
-use crate::context::{ActionReq, DapSnapShot, DebugAction, Message, MessageCMD};
+use crate::{
+    context::{ActionReq, DapSnapShot, DebugAction, Message, MessageCMD, StepInGoal},
+    handler::{RequestHandlerError, step_back_request::replay_forward},
+};
 
 use super::RequestResult;
 
-pub async fn on_pause_request(dap: DapSnapShot, _: (), _: CancellationToken) -> RequestResult {
+/// steps spent walking through other calls of the line before giving up
+const MAX_STEP_IN_GOAL_STEPS: usize = 500;
+
+pub async fn on_pause_request(
+    dap: DapSnapShot,
+    pause_arguments: PauseArguments,
+    _: CancellationToken,
+) -> RequestResult {
     log::info!("Received Pause request");
-    let debugger_conn = dap.debugger_conn.lock().await;
-    debugger_conn
-        .send_message(Message::ActionReq(ActionReq {
-            cmd: MessageCMD::ActionReq as i64,
-            action: DebugAction::Break,
-        }))
-        .await?;
+    send_action(&dap, pause_arguments.thread_id, DebugAction::Break).await?;
 
     Ok(ResponseBody::Pause)
 }
 
-pub async fn on_continue_request(dap: DapSnapShot, _: (), _: CancellationToken) -> RequestResult {
+pub async fn on_continue_request(
+    dap: DapSnapShot,
+    continue_arguments: ContinueArguments,
+    _: CancellationToken,
+) -> RequestResult {
     log::info!("Received Continue request");
-    let debugger_conn = dap.debugger_conn.lock().await;
-    debugger_conn
-        .send_message(Message::ActionReq(ActionReq {
-            cmd: MessageCMD::ActionReq as i64,
-            action: DebugAction::Continue,
-        }))
-        .await?;
+    send_action(&dap, continue_arguments.thread_id, DebugAction::Continue).await?;
 
     Ok(ResponseBody::Continue(ContinueResponse {
-        all_threads_continued: None,
+        all_threads_continued: Some(dap.target_count() == 1),
     }))
 }
 
-pub async fn on_step_in_request(dap: DapSnapShot, _: (), _: CancellationToken) -> RequestResult {
+pub async fn on_step_in_request(
+    dap: DapSnapShot,
+    step_in_arguments: StepInArguments,
+    _: CancellationToken,
+) -> RequestResult {
     log::info!("Received StepIn request");
-    let debugger_conn = dap.debugger_conn.lock().await;
-    debugger_conn
-        .send_message(Message::ActionReq(ActionReq {
-            cmd: MessageCMD::ActionReq as i64,
-            action: DebugAction::StepIn,
-        }))
-        .await?;
+    send_step(
+        &dap,
+        step_in_arguments.thread_id,
+        DebugAction::StepIn,
+        step_in_arguments.target_id,
+    )
+    .await?;
 
     Ok(ResponseBody::StepIn)
 }
 
-pub async fn on_step_out_request(dap: DapSnapShot, _: (), _: CancellationToken) -> RequestResult {
+pub async fn on_step_out_request(
+    dap: DapSnapShot,
+    step_out_arguments: StepOutArguments,
+    _: CancellationToken,
+) -> RequestResult {
     log::info!("Received StepOut request");
-    let debugger_conn = dap.debugger_conn.lock().await;
-    debugger_conn
-        .send_message(Message::ActionReq(ActionReq {
-            cmd: MessageCMD::ActionReq as i64,
-            action: DebugAction::StepOut,
-        }))
-        .await?;
+    send_action(&dap, step_out_arguments.thread_id, DebugAction::StepOut).await?;
 
     Ok(ResponseBody::StepOut)
 }
 
-pub async fn on_next_request(dap: DapSnapShot, _: (), _: CancellationToken) -> RequestResult {
+pub async fn on_next_request(
+    dap: DapSnapShot,
+    next_arguments: NextArguments,
+    _: CancellationToken,
+) -> RequestResult {
     log::info!("Received Next request");
+    send_action(&dap, next_arguments.thread_id, DebugAction::StepOver).await?;
+
+    Ok(ResponseBody::Next)
+}
+
+/// Actions go to the lua state owning the thread, the others keep running.
+pub async fn send_action(
+    dap: &DapSnapShot,
+    thread_id: i64,
+    action: DebugAction,
+) -> Result<(), Box<dyn std::error::Error + Send>> {
+    send_step(dap, thread_id, action, None).await
+}
+
+/// `step_in_target` is a target id from stepInTargets, the break notification keeps
+/// stepping until that call is entered.
+async fn send_step(
+    dap: &DapSnapShot,
+    thread_id: i64,
+    action: DebugAction,
+    step_in_target: Option<i64>,
+) -> Result<(), Box<dyn std::error::Error + Send>> {
+    let Some((dap, _)) = dap.thread_target(thread_id) else {
+        return Err(RequestHandlerError::Message(format!("Unknown thread {}", thread_id)).into());
+    };
+    // while a recorded stop is shown, stepping forward walks the history back to the
+    // live stop before the debuggee runs again
+    if action != DebugAction::Break && replay_forward(&dap, action == DebugAction::Continue).await {
+        return Ok(());
+    }
+    {
+        let mut data = dap.data.lock().await;
+        data.step_action = match action {
+            DebugAction::StepIn | DebugAction::StepOver | DebugAction::StepOut => Some(action),
+            _ => None,
+        };
+        data.skipped_steps = 0;
+        data.step_in_goal = match step_in_target {
+            Some(target_id) if target_id > 0 => {
+                let function_name = data.step_in_targets.get(target_id as usize - 1).cloned();
+                function_name.map(|function_name| StepInGoal {
+                    function_name,
+                    depth: data.stacks.len(),
+                    steps_left: MAX_STEP_IN_GOAL_STEPS,
+                })
+            }
+            _ => None,
+        };
+    }
     let debugger_conn = dap.debugger_conn.lock().await;
     debugger_conn
         .send_message(Message::ActionReq(ActionReq {
             cmd: MessageCMD::ActionReq as i64,
-            action: DebugAction::StepOver,
+            action,
         }))
         .await?;
-
-    Ok(ResponseBody::Next)
+    drop(debugger_conn);
+    if action != DebugAction::Break {
+        dap.data.lock().await.resume();
+    }
+    Ok(())
 }
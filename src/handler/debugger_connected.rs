@@ -1,6 +1,7 @@
 use std::error::Error;
 
 use dap::events::{Event, OutputEventBody};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     context::{AddBreakPointReq, DapSnapShot, InitReq, Message, MessageCMD, ReadyReq},
@@ -13,10 +14,15 @@ pub async fn after_debugger_connected(
 ) -> Result<(), Box<dyn Error + Send>> {
     {
         log::info!("on debugger connected");
+        let output = if dap.target_id == 0 {
+            "Debugger connected\n".to_string()
+        } else {
+            format!("Debugger connected: {}\n", dap.target_name())
+        };
         let mut ide_conn = dap.ide_conn.lock().unwrap();
         match ide_conn.send_event(Event::Output(OutputEventBody {
             category: Some(dap::types::OutputEventCategory::Console),
-            output: "Debugger connected\n".to_string(),
+            output,
             ..Default::default()
         })) {
             Ok(_) => {}
@@ -27,22 +33,57 @@ pub async fn after_debugger_connected(
         }
     }
 
+    send_initial_state(&dap, ext).await?;
+
+    // the editor configures the session once, later targets get the breakpoints above
+    if dap.target_id == 0 {
+        log::info!("send initialized event to ide");
+        let mut ide_conn = dap.ide_conn.lock().unwrap();
+        match ide_conn.send_event(Event::Initialized) {
+            Ok(_) => {
+                log::info!("Successfully sent initialized event");
+            }
+            Err(err) => {
+                log::error!("Failed to send initialized event: {:?}", err);
+                return Err(RequestHandlerError::ServerError(err).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Hand the debuggee the file extensions and breakpoints, then let it run. Sent on
+/// every new link to a lua state, reconnects included.
+pub async fn send_initial_state(
+    dap: &DapSnapShot,
+    ext: Vec<String>,
+) -> Result<(), Box<dyn Error + Send>> {
     {
         log::info!("send init req to debugger");
-        let debugger_conn = dap.debugger_conn.lock().await;
-        log::info!("get debugger_conn lock");
-        debugger_conn
-            .send_message(Message::InitReq(InitReq {
-                cmd: MessageCMD::InitReq as i64,
-                emmy_helper: "".to_string(),
-                ext,
-            }))
-            .await?;
+        let requests = dap.debugger_conn.lock().await.requests();
+        let init_rsp = requests
+            .send_request(
+                Message::InitReq(InitReq {
+                    cmd: MessageCMD::InitReq as i64,
+                    emmy_helper: "".to_string(),
+                    ext,
+                }),
+                &CancellationToken::new(),
+            )
+            .await;
+        // every emmy_core gets the same protocol, the version is only logged
+        match init_rsp {
+            Ok(Message::InitRsp(init_rsp)) => {
+                log::info!("{} runs emmy_core {}", dap.target_name(), init_rsp.version)
+            }
+            Ok(_) => {}
+            Err(err) => log::warn!("No InitRsp from {}: {}", dap.target_name(), err),
+        }
 
         log::info!("Sending all breakpoints to debugger");
         let data = dap.data.lock().await;
         let breakpoints = data.breakpoints.values().cloned().collect::<Vec<_>>();
-        debugger_conn
+        requests
             .send_message(Message::AddBreakPointReq(AddBreakPointReq {
                 cmd: MessageCMD::AddBreakPointReq as i64,
                 break_points: breakpoints,
@@ -51,25 +92,11 @@ pub async fn after_debugger_connected(
             .await?;
 
         log::info!("Sending ready req to debugger");
-        debugger_conn
+        requests
             .send_message(Message::ReadyReq(ReadyReq {
                 cmd: MessageCMD::ReadyReq as i64,
             }))
             .await?;
     }
-
-    {
-        log::info!("send initialized event to ide");
-        let mut ide_conn = dap.ide_conn.lock().unwrap();
-        match ide_conn.send_event(Event::Initialized) {
-            Ok(_) => {
-                log::info!("Successfully sent initialized event");
-            }
-            Err(err) => {
-                log::error!("Failed to send initialized event: {:?}", err);
-                return Err(RequestHandlerError::ServerError(err).into());
-            }
-        }
-    }
     Ok(())
 }
@@ -3,7 +3,21 @@ use dap::{
     types::StoppedEventReason,
 };
 
-use crate::context::{DapSnapShot, LogNotify, Message, MessageCMD};
+use crate::{
+    context::{
+        ActionReq, BreakPoint, DapSnapShot, DebugAction, DebuggerData, LogNotify, Message,
+        MessageCMD, StepInGoal, chunk_matches, to_target_thread_id,
+    },
+    handler::{
+        coroutine_threads::refresh_coroutines,
+        set_breakpoint_request::send_target_breakpoints,
+        stack_trace_request::find_file_path,
+        step_back_request::{record_stop, record_top_frame_tables},
+    },
+};
+
+/// how many times a step in may follow into skipped code before stepping out of it
+const MAX_SKIPPED_STEP_INS: usize = 100;
 
 pub async fn register_debugger_notification(dap: DapSnapShot) {
     let debugger_conn = dap.debugger_conn.lock().await;
@@ -16,24 +30,80 @@ pub async fn register_debugger_notification(dap: DapSnapShot) {
 
     if let Some(mut break_hit_receiver) = break_hit_notification {
         let ide_conn = dap.ide_conn.clone();
-        let data = dap.data.clone();
+        let dap = dap.clone();
         tokio::spawn(async move {
             while let Some(break_hit) = break_hit_receiver.recv().await {
                 log::info!("Received Break Hit notification: {:#?}", break_hit);
                 if let Message::BreakNotify(break_hit) = break_hit {
-                    {
-                        let mut data = data.lock().await;
+                    let (skip_step, by_step) = {
+                        let mut data = dap.data.lock().await;
+                        let by_step = data.step_action.is_some();
                         data.stacks = break_hit.stacks;
+                        let skip_step =
+                            next_step_in_goal_step(&mut data).or_else(|| next_skip_step(&mut data));
+                        (skip_step, by_step)
+                    };
+                    // the stop is part of a step the adapter drives, keep going without
+                    // telling the editor
+                    if let Some(action) = skip_step {
+                        log::info!("Continuing adapter driven step with {:?}", action);
+                        let sent = dap
+                            .debugger_conn
+                            .lock()
+                            .await
+                            .send_message(Message::ActionReq(ActionReq {
+                                cmd: MessageCMD::ActionReq as i64,
+                                action,
+                            }))
+                            .await;
+                        match sent {
+                            Ok(_) => {
+                                dap.data.lock().await.resume();
+                                continue;
+                            }
+                            Err(err) => {
+                                log::error!("Failed to continue step: {}", err);
+                            }
+                        }
+                    }
+
+                    let run_to_breakpoint = dap.data.lock().await.run_to_breakpoint.take();
+                    let reason = match run_to_breakpoint {
+                        Some(breakpoint) => {
+                            send_target_breakpoints(dap.clone()).await;
+                            if is_at_breakpoint(&*dap.data.lock().await, &breakpoint) {
+                                StoppedEventReason::Goto
+                            } else {
+                                StoppedEventReason::String("breakpoint".to_string())
+                            }
+                        }
+                        None => StoppedEventReason::String("breakpoint".to_string()),
+                    };
+
+                    // a step or run to line may stop on a line with an inline breakpoint
+                    // without the breakpoint being the reason
+                    let stop_column = match reason {
+                        StoppedEventReason::String(_) if !by_step => breakpoint_column(&dap).await,
+                        _ => None,
+                    };
+                    let stopped_thread_id = refresh_coroutines(&dap).await;
+                    {
+                        let mut data = dap.data.lock().await;
+                        data.stopped_thread_id = stopped_thread_id;
+                        data.stop_column = stop_column;
+                        data.stop_seq += 1;
                     }
+                    dap.set_focused();
+                    record_stop(&dap, by_step).await;
 
                     let mut ide_conn = ide_conn.lock().unwrap();
                     log::info!("Sending Break Hit notification to IDE");
                     match ide_conn.send_event(Event::Stopped(StoppedEventBody {
-                        reason: StoppedEventReason::String("breakpoint".to_string()),
-                        thread_id: Some(1),
+                        reason,
+                        thread_id: Some(to_target_thread_id(dap.target_id, stopped_thread_id)),
                         description: None,
                         text: None,
-                        all_threads_stopped: None,
+                        all_threads_stopped: Some(dap.target_count() == 1),
                         preserve_focus_hint: None,
                         hit_breakpoint_ids: None,
                     })) {
@@ -42,6 +112,9 @@ pub async fn register_debugger_notification(dap: DapSnapShot) {
                             log::error!("Failed to send Break Hit notification: {:?}", err);
                         }
                     }
+                    drop(ide_conn);
+                    let dap = dap.clone();
+                    tokio::spawn(async move { record_top_frame_tables(&dap).await });
                 }
             }
         });
@@ -73,3 +146,136 @@ pub async fn register_debugger_notification(dap: DapSnapShot) {
         log::error!("Failed to register Log notification");
     }
 }
+
+/// Steer a step towards the call chosen from stepInTargets. Lua reports no line when a
+/// call returns to the same line, so calls before the chosen one are walked through with
+/// step in, anything they call is left with step out.
+fn next_step_in_goal_step(data: &mut DebuggerData) -> Option<DebugAction> {
+    let goal = data.step_in_goal.take()?;
+    let depth = data.stacks.len();
+    let top = data.stacks.first()?;
+    // the line is done, or the chosen call was entered
+    if depth <= goal.depth
+        || goal.steps_left == 0
+        || (depth == goal.depth + 1 && top.function_name == goal.function_name)
+    {
+        return None;
+    }
+
+    let action = if depth > goal.depth + 1 {
+        DebugAction::StepOut
+    } else {
+        DebugAction::StepIn
+    };
+    data.step_in_goal = Some(StepInGoal {
+        steps_left: goal.steps_left - 1,
+        ..goal
+    });
+    Some(action)
+}
+
+/// Work out whether a step stopped in a skipped file and which action leaves it.
+/// Stepping in keeps stepping in so callbacks into user code are still reached. A
+/// breakpoint the user set in a skipped file stops the step like anywhere else.
+fn next_skip_step(data: &mut DebuggerData) -> Option<DebugAction> {
+    let step_action = data.step_action?;
+    let in_skipped_file = data
+        .stacks
+        .first()
+        .is_some_and(|stack| chunk_matches(&stack.file, &data.skip_files));
+    let at_breakpoint = in_skipped_file
+        && data
+            .breakpoints
+            .values()
+            .any(|breakpoint| is_at_breakpoint(data, breakpoint));
+    if !in_skipped_file || at_breakpoint {
+        data.step_action = None;
+        data.skipped_steps = 0;
+        return None;
+    }
+
+    data.skipped_steps += 1;
+    if step_action == DebugAction::StepIn && data.skipped_steps <= MAX_SKIPPED_STEP_INS {
+        Some(DebugAction::StepIn)
+    } else {
+        Some(DebugAction::StepOut)
+    }
+}
+
+/// Column of the inline breakpoint on the top frame's line, if it has one.
+async fn breakpoint_column(dap: &DapSnapShot) -> Option<i64> {
+    let mut data = dap.data.lock().await;
+    let top = data.stacks.first()?;
+    let (file, line) = (top.file.clone(), top.line as i64);
+    let path = find_file_path(&mut data, file).await.ok()??;
+    data.breakpoint_columns.get(&(path, line)).copied()
+}
+
+/// Whether the top frame is the location of a run to line breakpoint, the chunk name
+/// may not be resolved to a file yet so its path suffix is compared as well.
+fn is_at_breakpoint(data: &DebuggerData, breakpoint: &BreakPoint) -> bool {
+    let Some(top) = data.stacks.first() else {
+        return false;
+    };
+    if top.line != breakpoint.line {
+        return false;
+    }
+    if let Some(Some(path)) = data.file_cache.get(&top.file) {
+        return *path == breakpoint.file;
+    }
+    let chunkname = top.file.strip_prefix('@').unwrap_or(&top.file);
+    breakpoint
+        .file
+        .replace('\\', "/")
+        .ends_with(&chunkname.replace('\\', "/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::Stack;
+
+    use super::*;
+
+    fn stopped_in(file: &str, line: i32) -> DebuggerData {
+        DebuggerData {
+            stacks: vec![Stack {
+                file: file.to_string(),
+                line,
+                function_name: "f".to_string(),
+                level: 0,
+                local_variables: vec![],
+                upvalue_variables: vec![],
+            }],
+            skip_files: vec![glob::Pattern::new("lib/**").unwrap()],
+            step_action: Some(DebugAction::StepOver),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn steps_out_of_skipped_files() {
+        let mut data = stopped_in("@lib/json.lua", 3);
+        assert_eq!(next_skip_step(&mut data), Some(DebugAction::StepOut));
+
+        let mut data = stopped_in("@main.lua", 3);
+        assert_eq!(next_skip_step(&mut data), None);
+        assert_eq!(data.step_action, None);
+    }
+
+    #[test]
+    fn stops_at_breakpoints_in_skipped_files() {
+        let mut data = stopped_in("@lib/json.lua", 3);
+        data.breakpoints.insert(
+            ("/work/lib/json.lua".to_string(), 3),
+            BreakPoint {
+                file: "/work/lib/json.lua".to_string(),
+                line: 3,
+                condition: None,
+                hit_condition: None,
+                log_message: None,
+            },
+        );
+        assert_eq!(next_skip_step(&mut data), None);
+        assert_eq!(data.step_action, None);
+    }
+}
@@ -1,53 +1,456 @@
+use std::error::Error;
+
 use dap::{
     requests::EvaluateArguments,
     responses::{EvaluateResponse, ResponseBody},
+    types::{
+        EvaluateArgumentsContext, VariablePresentationHint, VariablePresentationHintAttributes,
+    },
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::context::{DapSnapShot, DebuggerCacheItem, DebuggerCacheRef, DebuggerVariable};
+use crate::{
+    context::{
+        DapSnapShot, DebuggerCacheItem, DebuggerCacheRef, DebuggerData, DebuggerVariable,
+        ValueType, Variable, WatchValue, format_string_value, serialize_variable,
+        split_thread_frame_id,
+    },
+    handler::{
+        RequestHandlerError,
+        console_command::{is_console_command, on_console_command},
+        step_back_request::replaying_error,
+    },
+    lua_syntax::{
+        assigned_names, contains_keyword, has_side_effects, is_statement, may_return_multiple,
+    },
+};
 
 use super::RequestResult;
 
+/// how deep tables are fetched when the editor copies a value
+const CLIPBOARD_EVAL_DEPTH: i64 = 3;
+/// global used to carry locals modified by a console statement back to the frame
+const REPL_RESULT_GLOBAL: &str = "__emmy_repl_result";
+
 pub async fn on_evaluate_request(
     dap: DapSnapShot,
     evaluate_arguments: EvaluateArguments,
-    _: CancellationToken,
+    cancel: CancellationToken,
 ) -> RequestResult {
-    let frame_id = evaluate_arguments.frame_id.unwrap_or(-1);
+    let Some((dap, frame_id)) = dap.frame_target(evaluate_arguments.frame_id) else {
+        return Err(RequestHandlerError::Message("Unknown stack frame".to_string()).into());
+    };
+    let editor_frame_id = frame_id;
+    let frame_id = frame_id.unwrap_or(-1);
+    let context = evaluate_arguments.context;
     let expression = evaluate_arguments.expression;
-
-    let mut debugger_conn = dap.debugger_conn.lock().await;
-    let eval_rsp = debugger_conn.eval_expr(expression, 0, 1, frame_id).await?;
-
-    if eval_rsp.success {
-        let mut data = dap.data.lock().await;
-        let value = eval_rsp.value.unwrap();
-        let ref_id = data.cache.allocate_cache_id();
-        let variable_item = DebuggerCacheItem::Variable(
-            DebuggerCacheRef::new(
-                ref_id,
-                DebuggerVariable {
-                    var: value,
-                    parent_ref_id: 0,
-                },
+    if split_thread_frame_id(frame_id).is_some() && !is_console_command(&expression) {
+        return Err(other_thread_frame_error());
+    }
+    if dap.data.lock().await.history.is_replaying() && !is_console_command(&expression) {
+        return Err(replaying_error());
+    }
+    match context {
+        Some(EvaluateArgumentsContext::Repl) => {
+            if is_console_command(&expression) {
+                return on_console_command(dap, &expression).await;
+            }
+            return evaluate_repl(dap, expression, editor_frame_id, &cancel).await;
+        }
+        Some(EvaluateArgumentsContext::Hover) if has_side_effects(&expression) => {
+            return Err(RequestHandlerError::Message(
+                "Hover evaluation skipped: expression may have side effects".to_string(),
             )
-            .into(),
-        );
-        let variable = variable_item.to_dap_variable();
-        data.cache.add_cache(variable_item);
+            .into());
+        }
+        _ => {}
+    }
 
-        Ok(ResponseBody::Evaluate(EvaluateResponse {
-            result: variable.value,
-            type_field: variable.type_field,
-            variables_reference: ref_id,
-            ..Default::default()
-        }))
+    // copy value wants the whole value, not the truncated one shown in the ui
+    let is_clipboard = matches!(context, Some(EvaluateArgumentsContext::Clipboard));
+    let depth = if is_clipboard {
+        CLIPBOARD_EVAL_DEPTH
     } else {
-        Ok(ResponseBody::Evaluate(EvaluateResponse {
-            result: eval_rsp.error.unwrap_or("".to_string()),
-            type_field: Some("string".to_string()),
-            variables_reference: 0,
+        1
+    };
+
+    let watch_expression =
+        matches!(context, Some(EvaluateArgumentsContext::Watch)).then(|| expression.clone());
+    match eval(&dap, expression, depth, frame_id, &cancel).await? {
+        Ok(value) => {
+            if is_clipboard {
+                return Ok(ResponseBody::Evaluate(EvaluateResponse {
+                    result: serialize_variable(&value),
+                    variables_reference: 0,
+                    ..Default::default()
+                }));
+            }
+
+            let mut data = dap.data.lock().await;
+            let response = variable_response(&mut data, value);
+            match watch_expression {
+                Some(expression) => Ok(mark_watch_change(&mut data, expression, response)),
+                None => Ok(response),
+            }
+        }
+        Err(error) => match context {
+            // watch and hover show failures as errors instead of a string value
+            Some(EvaluateArgumentsContext::Watch)
+            | Some(EvaluateArgumentsContext::Hover)
+            | Some(EvaluateArgumentsContext::Clipboard) => {
+                Err(RequestHandlerError::Message(error).into())
+            }
+            _ => Ok(error_response(error)),
+        },
+    }
+}
+
+async fn evaluate_repl(
+    dap: DapSnapShot,
+    input: String,
+    frame_id: Option<i64>,
+    cancel: &CancellationToken,
+) -> RequestResult {
+    // without a frame from the editor follow the one switched to with `.frame`
+    let frame_id = match frame_id {
+        Some(frame_id) => frame_id,
+        None => {
+            let data = dap.data.lock().await;
+            if !data.is_stopped() {
+                -1
+            } else {
+                data.current_frame_id
+            }
+        }
+    };
+    if split_thread_frame_id(frame_id).is_some() {
+        return Err(other_thread_frame_error());
+    }
+    let chunk = normalize_repl_input(&input);
+    if is_statement(&chunk) {
+        return execute_statement(dap, chunk, frame_id, cancel).await;
+    }
+
+    if !may_return_multiple(&chunk) {
+        return match eval(&dap, chunk, 1, frame_id, cancel).await? {
+            Ok(value) => {
+                let mut data = dap.data.lock().await;
+                Ok(variable_response(&mut data, value))
+            }
+            Err(error) => Ok(error_response(error)),
+        };
+    }
+
+    // pack every returned value so none is dropped by the debugger
+    let packed_expr = format!(
+        "(function(...) return {{n = select(\"#\", ...), ...}} end)({})",
+        chunk
+    );
+    match eval(&dap, packed_expr, 2, frame_id, cancel).await? {
+        Ok(packed) => {
+            let mut data = dap.data.lock().await;
+            let max_string_length = data.cache.max_string_length;
+            match unpack_values(packed, max_string_length) {
+                Some(value) => Ok(variable_response(&mut data, value)),
+                None => Ok(ResponseBody::Evaluate(EvaluateResponse {
+                    result: "".to_string(),
+                    variables_reference: 0,
+                    ..Default::default()
+                })),
+            }
+        }
+        Err(error) => Ok(error_response(error)),
+    }
+}
+
+/// Run a statement in the frame environment. Locals and upvalues it assigns are shadowed
+/// inside the chunk, returned through a temporary global and then written back to the frame.
+async fn execute_statement(
+    dap: DapSnapShot,
+    chunk: String,
+    frame_id: i64,
+    cancel: &CancellationToken,
+) -> RequestResult {
+    let frame_names = {
+        let data = dap.data.lock().await;
+        match data.get_stack(frame_id) {
+            Some(stack) => stack
+                .local_variables
+                .iter()
+                .chain(stack.upvalue_variables.iter())
+                .map(|var| var.name.clone())
+                .collect::<Vec<_>>(),
+            None => vec![],
+        }
+    };
+
+    let assigned = assigned_names(&chunk)
+        .into_iter()
+        .filter(|name| frame_names.contains(name))
+        .collect::<Vec<_>>();
+    // a `return` in the chunk would make the trailing return unreachable code
+    let returns = contains_keyword(&chunk, "return");
+    if assigned.is_empty() || returns {
+        let note = if assigned.is_empty() {
+            "".to_string()
+        } else {
+            format!(
+                "{} not written back to the frame: the statement contains `return`",
+                assigned.join(", ")
+            )
+        };
+        let wrapped = format!("(function(...)\n{}\nend)()", chunk);
+        return match eval(&dap, wrapped, 1, frame_id, cancel).await? {
+            Ok(_) => Ok(ResponseBody::Evaluate(EvaluateResponse {
+                result: note,
+                variables_reference: 0,
+                ..Default::default()
+            })),
+            Err(error) => Ok(error_response(error)),
+        };
+    }
+
+    let write_back = assigned;
+    let names = write_back.join(", ");
+    let wrapped = format!(
+        "rawset(_G, \"{}\", {{(function(...)\nlocal {} = {}\n{}\nreturn {}\nend)()}})",
+        REPL_RESULT_GLOBAL, names, names, chunk, names
+    );
+    if let Err(error) = eval(&dap, wrapped, 1, frame_id, cancel).await? {
+        return Ok(error_response(error));
+    }
+
+    // the statement ran, finish writing back and cleaning up even if the request is
+    // cancelled now
+    let finish = CancellationToken::new();
+    let mut errors = vec![];
+    let requests = dap.debugger_conn.lock().await.requests();
+    for (i, name) in write_back.iter().enumerate() {
+        let value = format!("{}[{}]", REPL_RESULT_GLOBAL, i + 1);
+        let rsp = requests
+            .set_value(name.clone(), value, frame_id, &finish)
+            .await?;
+        if !rsp.success {
+            errors.push(format!(
+                "failed to write back {}: {}",
+                name,
+                clean_eval_error(&rsp.error.unwrap_or_default())
+            ));
+        }
+    }
+
+    let cleanup = format!("rawset(_G, \"{}\", nil)", REPL_RESULT_GLOBAL);
+    if let Err(error) = eval(&dap, cleanup, 1, frame_id, &finish).await? {
+        log::warn!("Failed to clean up repl result: {}", error);
+    }
+
+    Ok(ResponseBody::Evaluate(EvaluateResponse {
+        result: errors.join("\n"),
+        variables_reference: 0,
+        ..Default::default()
+    }))
+}
+
+/// Evaluate in the debuggee, the inner result holds the cleaned up lua error.
+async fn eval(
+    dap: &DapSnapShot,
+    expression: String,
+    depth: i64,
+    frame_id: i64,
+    cancel: &CancellationToken,
+) -> Result<Result<Variable, String>, Box<dyn Error + Send>> {
+    let requests = dap.debugger_conn.lock().await.requests();
+    let eval_rsp = requests
+        .eval_expr(expression, 0, depth, frame_id, cancel)
+        .await?;
+    match eval_rsp.value {
+        Some(value) if eval_rsp.success => Ok(Ok(value)),
+        _ => Ok(Err(clean_eval_error(&eval_rsp.error.unwrap_or_default()))),
+    }
+}
+
+/// Flag a watch whose value differs from the one shown at the previous stop, the old
+/// value is kept next to the new one.
+fn mark_watch_change(
+    data: &mut DebuggerData,
+    expression: String,
+    response: ResponseBody,
+) -> ResponseBody {
+    let ResponseBody::Evaluate(mut evaluate_response) = response else {
+        return response;
+    };
+    let stop_seq = data.stop_seq;
+    let result = evaluate_response.result.clone();
+    let old_value = match data.watch_values.get_mut(&expression) {
+        Some(watch_value) => watch_value.update(stop_seq, result),
+        None => {
+            data.watch_values
+                .insert(expression, WatchValue::new(stop_seq, result));
+            None
+        }
+    };
+
+    if let Some(old_value) = old_value {
+        evaluate_response.result = format!("{} (was {})", evaluate_response.result, old_value);
+        evaluate_response.presentation_hint = Some(VariablePresentationHint {
+            attributes: Some(vec![VariablePresentationHintAttributes::String(
+                "changed".to_string(),
+            )]),
             ..Default::default()
-        }))
+        });
+    }
+    ResponseBody::Evaluate(evaluate_response)
+}
+
+fn variable_response(data: &mut DebuggerData, value: Variable) -> ResponseBody {
+    let ref_id = data.cache.allocate_cache_id();
+    let variable_item = DebuggerCacheItem::Variable(
+        DebuggerCacheRef::new(
+            ref_id,
+            DebuggerVariable {
+                var: value,
+                parent_ref_id: 0,
+            },
+        )
+        .into(),
+    );
+    let variable = variable_item.to_dap_variable(Some(data.cache.max_string_length));
+    data.cache.add_cache(variable_item);
+
+    ResponseBody::Evaluate(EvaluateResponse {
+        result: variable.value,
+        type_field: variable.type_field,
+        variables_reference: variable.variables_reference,
+        ..Default::default()
+    })
+}
+
+/// the debugger evaluates in the running coroutine only
+fn other_thread_frame_error() -> Box<dyn Error + Send> {
+    RequestHandlerError::Message(
+        "Evaluation is only available in frames of the stopped thread".to_string(),
+    )
+    .into()
+}
+
+fn error_response(error: String) -> ResponseBody {
+    ResponseBody::Evaluate(EvaluateResponse {
+        result: error,
+        type_field: Some("string".to_string()),
+        variables_reference: 0,
+        ..Default::default()
+    })
+}
+
+/// Turn the `{n = select("#", ...), ...}` table into a single value, or a group
+/// with one indexed child per returned value. `None` when nothing was returned.
+fn unpack_values(packed: Variable, max_string_length: usize) -> Option<Variable> {
+    let children = packed.children.unwrap_or_default();
+    let count = children
+        .iter()
+        .find(|child| child.name_type == ValueType::TSTRING && child.name == "n")
+        .and_then(|child| child.value.parse::<f64>().ok())
+        .unwrap_or(0.0) as usize;
+
+    let mut values = (1..=count)
+        .map(|index| {
+            let name = index.to_string();
+            children
+                .iter()
+                .find(|child| child.name_type != ValueType::TSTRING && child.name == name)
+                .cloned()
+                .unwrap_or_else(|| Variable {
+                    name,
+                    name_type: ValueType::TNUMBER,
+                    value: "nil".to_string(),
+                    value_type: ValueType::TNIL,
+                    value_type_name: "nil".to_string(),
+                    cache_id: 0,
+                    children: None,
+                })
+        })
+        .collect::<Vec<_>>();
+
+    match values.len() {
+        0 => None,
+        1 => values.pop(),
+        _ => {
+            let summary = values
+                .iter()
+                .map(|value| match value.value_type {
+                    ValueType::TSTRING => {
+                        format_string_value(&value.value, Some(max_string_length))
+                    }
+                    _ => value.value.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            Some(Variable {
+                name: "".to_string(),
+                name_type: ValueType::TSTRING,
+                value: summary,
+                value_type: ValueType::GROUP,
+                value_type_name: "values".to_string(),
+                cache_id: 0,
+                children: Some(values),
+            })
+        }
+    }
+}
+
+/// The debugger evaluates `return <expr>`, so `return x` typed in the console is
+/// unwrapped to the expression itself.
+fn normalize_repl_input(input: &str) -> String {
+    let input = input.trim().trim_end_matches(';').trim_end();
+    if let Some(rest) = input.strip_prefix("return")
+        && (rest.is_empty() || rest.starts_with(char::is_whitespace))
+        && !is_statement(rest)
+    {
+        return rest.trim().to_string();
+    }
+
+    input.to_string()
+}
+
+/// Strip the `[string "..."]:1:` prefix lua adds to errors of evaluated chunks.
+fn clean_eval_error(error: &str) -> String {
+    if let Some(rest) = error.strip_prefix("[string \"")
+        && let Some(pos) = rest.find("\"]:")
+    {
+        let rest = rest[pos + 3..].trim_start_matches(|c: char| c.is_ascii_digit());
+        if let Some(message) = rest.strip_prefix(": ") {
+            return message.to_string();
+        }
+    }
+
+    error.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleans_eval_errors() {
+        assert_eq!(
+            clean_eval_error("[string \"return a.b\"]:1: attempt to index a nil value"),
+            "attempt to index a nil value"
+        );
+        assert_eq!(
+            clean_eval_error("[string \"...\"]:12: unexpected symbol"),
+            "unexpected symbol"
+        );
+        assert_eq!(clean_eval_error("plain error"), "plain error");
+        assert_eq!(clean_eval_error("[string \"x"), "[string \"x");
+    }
+
+    #[test]
+    fn normalizes_repl_input() {
+        assert_eq!(normalize_repl_input("  return a + 1; "), "a + 1");
+        assert_eq!(normalize_repl_input("return"), "");
+        assert_eq!(normalize_repl_input("returned"), "returned");
+        assert_eq!(normalize_repl_input("a = 1;"), "a = 1");
+        // `return a = 1` is no expression, it is left for lua to report
+        assert_eq!(normalize_repl_input("return a = 1"), "return a = 1");
     }
 }
@@ -0,0 +1,99 @@
+use dap::{
+    requests::{GotoArguments, GotoTargetsArguments},
+    responses::{GotoTargetsResponse, ResponseBody},
+    types::GotoTarget,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    context::{BreakPoint, DapSnapShot, DebugAction},
+    handler::{
+        RequestHandlerError, debug_action_request::send_action,
+        set_breakpoint_request::send_target_breakpoints, step_back_request::replaying_error,
+    },
+};
+
+use super::RequestResult;
+
+/// Lua can not move the program counter, a goto target runs to the line instead.
+/// gotoTargets carries no thread, so the targets are kept on the first debug target.
+pub async fn on_goto_targets_request(
+    dap: DapSnapShot,
+    goto_targets_arguments: GotoTargetsArguments,
+    _: CancellationToken,
+) -> RequestResult {
+    log::info!(
+        "Received GotoTargets request: {:#?}",
+        goto_targets_arguments
+    );
+    let Some(path) = goto_targets_arguments.source.path else {
+        return Ok(ResponseBody::GotoTargets(GotoTargetsResponse {
+            targets: vec![],
+        }));
+    };
+
+    let client = *dap.client.lock().unwrap();
+    let line = client.adapter_line(goto_targets_arguments.line);
+    let mut data = dap.data.lock().await;
+    let id = data.breakpoint_id;
+    data.breakpoint_id += 1;
+    data.goto_targets.clear();
+    data.goto_targets.insert(
+        id,
+        BreakPoint {
+            file: path,
+            line: line as i32,
+            condition: None,
+            hit_condition: None,
+            log_message: None,
+        },
+    );
+
+    Ok(ResponseBody::GotoTargets(GotoTargetsResponse {
+        targets: vec![GotoTarget {
+            id,
+            label: format!("Run to line {}", line),
+            line: client.client_line(line),
+            ..Default::default()
+        }],
+    }))
+}
+
+pub async fn on_goto_request(
+    dap: DapSnapShot,
+    goto_arguments: GotoArguments,
+    _: CancellationToken,
+) -> RequestResult {
+    log::info!("Received Goto request: {:#?}", goto_arguments);
+    let Some(breakpoint) = dap
+        .data
+        .lock()
+        .await
+        .goto_targets
+        .remove(&goto_arguments.target_id)
+    else {
+        return Err(RequestHandlerError::Message(format!(
+            "Unknown goto target {}",
+            goto_arguments.target_id
+        ))
+        .into());
+    };
+    let Some((target, _)) = dap.thread_target(goto_arguments.thread_id) else {
+        return Err(RequestHandlerError::Message(format!(
+            "Unknown thread {}",
+            goto_arguments.thread_id
+        ))
+        .into());
+    };
+
+    if target.data.lock().await.history.is_replaying() {
+        return Err(replaying_error());
+    }
+
+    // removed again by the next break, whichever breakpoint fires
+    target.data.lock().await.run_to_breakpoint = Some(breakpoint);
+    send_target_breakpoints(target).await;
+    send_action(&dap, goto_arguments.thread_id, DebugAction::Continue).await?;
+
+    Ok(ResponseBody::Goto)
+}
@@ -1,22 +1,37 @@
 use dap::{requests::InitializeArguments, responses::ResponseBody, types::Capabilities};
 use tokio_util::sync::CancellationToken;
 
-use crate::context::DapSnapShot;
+use crate::context::{ClientOptions, DapSnapShot};
 
 use super::RequestResult;
 
 pub async fn on_initialize_request(
-    _: DapSnapShot,
+    dap: DapSnapShot,
     initialize_arguments: InitializeArguments,
     _: CancellationToken,
 ) -> RequestResult {
     log::info!("Received Initialize request: {:#?}", initialize_arguments);
+    dap.progress.lock().unwrap().enabled = initialize_arguments
+        .supports_progress_reporting
+        .unwrap_or(false);
+    *dap.client.lock().unwrap() = ClientOptions {
+        lines_start_at1: initialize_arguments.lines_start_at1.unwrap_or(true),
+        columns_start_at1: initialize_arguments.columns_start_at1.unwrap_or(true),
+    };
 
     Ok(ResponseBody::Initialize(Capabilities {
         supports_evaluate_for_hovers: Some(true),
         support_terminate_debuggee: Some(true),
         supports_log_points: Some(true),
         supports_conditional_breakpoints: Some(true),
+        supports_clipboard_context: Some(true),
+        supports_completions_request: Some(true),
+        supports_goto_targets_request: Some(true),
+        supports_step_in_targets_request: Some(true),
+        // offered so the editor asks, every frame is refused with its reason
+        supports_restart_frame: Some(true),
+        supports_cancel_request: Some(true),
+        completion_trigger_characters: Some(vec![".".to_string(), ":".to_string()]),
         ..Default::default()
     }))
 }
@@ -1,11 +1,26 @@
-use dap::{requests::LaunchRequestArguments, responses::ResponseBody};
+use std::time::{Duration, Instant};
+
+use dap::{
+    events::{CapabilitiesEventBody, Event, OutputEventBody, ThreadEventBody},
+    requests::LaunchRequestArguments,
+    responses::ResponseBody,
+    types::{Capabilities, ThreadEventReason},
+};
+use glob::Pattern;
 use tokio_util::sync::CancellationToken;
 
 use crate::{
-    context::{DapSnapShot, EmmyNewDebugArguments},
+    context::{
+        ACCEPT_RETRY_DELAY, CONNECT_ATTEMPT_TIMEOUT, DapSnapShot, DebuggerConnection,
+        DebuggerEndpoint, EmmyNewDebugArguments, MAIN_THREAD_ID, PeerGuard, StopHistory,
+        TransportKind, tls_acceptor, tls_connector, to_target_thread_id,
+    },
     handler::{
-        RequestHandlerError, debugger_connected::after_debugger_connected,
+        RequestHandlerError,
+        debugger_connected::after_debugger_connected,
         debugger_notification::register_debugger_notification,
+        progress::Progress,
+        reconnect::{start_reconnect, start_relisten, start_target_exit_watch},
     },
 };
 
@@ -14,7 +29,7 @@ use super::RequestResult;
 pub async fn on_launch_request(
     dap: DapSnapShot,
     launch_arguments: LaunchRequestArguments,
-    _: CancellationToken,
+    cancel: CancellationToken,
 ) -> RequestResult {
     log::info!("Received Launch request: {:#?}", launch_arguments);
     // todo check mode
@@ -30,57 +45,351 @@ pub async fn on_launch_request(
     let emmy_new_debug_argument = serde_json::from_value::<EmmyNewDebugArguments>(additional)
         .map_err(|_| RequestHandlerError::Message("Failed to parse additional data".to_string()))?;
 
-    let mut debugger_conn = dap.debugger_conn.lock().await;
+    let library_files = compile_globs("libraryFiles", &emmy_new_debug_argument.library_files)?;
+    let skip_files = compile_globs("skipFiles", &emmy_new_debug_argument.skip_files)?;
 
-    let mut host = emmy_new_debug_argument.host;
-    if host == "localhost" {
-        host = "[::1]".into();
-    }
+    let endpoint = to_endpoint(&emmy_new_debug_argument)?;
+    // an inherited descriptor is connected already, whichever side started the link
+    let connects =
+        emmy_new_debug_argument.ide_connect_debugger || matches!(endpoint, DebuggerEndpoint::Fd(_));
 
-    let address = format!("{}:{}", host, emmy_new_debug_argument.port);
-    if emmy_new_debug_argument.ide_connect_debugger {
-        log::info!("Debugger connected to {}", address);
-        debugger_conn
-            .connect(&address, Some(5))
+    let request_timeout = Duration::from_millis(emmy_new_debug_argument.request_timeout_ms);
+    let mut debugger_conn = dap.debugger_conn.lock().await;
+    debugger_conn.set_request_timeout(request_timeout);
+    let max_message_size = emmy_new_debug_argument.max_message_size;
+    debugger_conn.set_max_message_size(max_message_size);
+
+    let tls = emmy_new_debug_argument.tls.as_ref();
+    let invalid_tls = |e| RequestHandlerError::Message(format!("Invalid tls configuration: {}", e));
+    let mut listener = None;
+    if connects {
+        log::info!("Connecting to debugger at {}", endpoint);
+        let connector = tls.map(tls_connector).transpose().map_err(invalid_tls)?;
+        debugger_conn.set_tls(connector);
+        connect_with_retry(
+            &dap,
+            &mut debugger_conn,
+            &endpoint,
+            &emmy_new_debug_argument,
+            &cancel,
+        )
+        .await?;
+    } else {
+        log::info!("Debugger listening on {}", endpoint);
+        let acceptor = tls.map(tls_acceptor).transpose().map_err(invalid_tls)?;
+        let auth = &emmy_new_debug_argument.auth;
+        let guard = PeerGuard::new(auth.secret.clone(), &auth.allowed_peers).map_err(|e| {
+            RequestHandlerError::Message(format!("Invalid auth configuration: {}", e))
+        })?;
+        let mut bound = DebuggerConnection::bind(&endpoint, acceptor)
             .await
             .map_err(|e| {
-                RequestHandlerError::Message(format!("Failed to connect to debugger: {}", e))
+                RequestHandlerError::Message(format!("Failed to listen on debugger: {}", e))
             })?;
-    } else {
-        log::info!("Debugger listening on {}", address);
-        debugger_conn.listen(&address).await.map_err(|e| {
+        bound.set_guard(guard);
+        let rejecting = dap.clone();
+        bound.on_reject(move |message| send_console_output(&rejecting, format!("{}\n", message)));
+        let peer = debugger_conn.accept(&bound).await.map_err(|e| {
             RequestHandlerError::Message(format!("Failed to listen on debugger: {}", e))
         })?;
+        send_console_output(&dap, format!("Debuggee connected from {}\n", peer));
+        listener = Some(bound);
     }
 
+    let reconnect = &emmy_new_debug_argument.reconnect;
+    let inherited = matches!(endpoint, DebuggerEndpoint::Fd(_));
+    if reconnect.enabled && inherited {
+        log::warn!("An inherited descriptor can not be reconnected");
+    }
+    let reconnects = reconnect.enabled && connects && !inherited;
+    // in listen mode the listener either replaces the debuggee or adds targets, with
+    // reconnect off the session ends with the first debuggee
+    let relistens =
+        reconnect.enabled && listener.is_some() && !emmy_new_debug_argument.multiple_connections;
+    let disconnected = (reconnects || relistens).then(|| debugger_conn.watch_disconnect());
+
     log::info!("Debugger connection established, starting reader task");
     debugger_conn.start_reader_task(dap.ide_conn.clone());
+    let connector = debugger_conn.tls();
+    drop(debugger_conn);
+    dap.set_target_name(endpoint.to_string());
+
+    {
+        let mut data = dap.data.lock().await;
+        data.extension = emmy_new_debug_argument.ext.clone();
+        data.sources = emmy_new_debug_argument.source_paths.clone();
+        data.cache.max_string_length = emmy_new_debug_argument.max_string_length;
+        data.track_coroutines = emmy_new_debug_argument.track_coroutines;
+        data.library_files = library_files;
+        data.skip_files = skip_files;
+        if let Some(history) = &emmy_new_debug_argument.history {
+            data.history = StopHistory::new(history.size, history.depth);
+        }
+        // initialize comes before the launch configuration, step back is offered late
+        if data.history.is_enabled() {
+            dap.send_after_response(Event::Capabilities(CapabilitiesEventBody {
+                capabilities: Capabilities {
+                    supports_step_back: Some(true),
+                    ..Default::default()
+                },
+            }));
+        }
+    }
+
+    let ext = emmy_new_debug_argument.ext.clone();
+    start_target_session(dap.clone(), ext.clone());
+    if let Some(disconnected) = disconnected {
+        if reconnects {
+            start_reconnect(
+                dap.clone(),
+                disconnected,
+                endpoint.clone(),
+                reconnect.clone(),
+                ext.clone(),
+            );
+        } else if let Some(listener) = listener.take() {
+            start_relisten(dap.clone(), disconnected, listener, ext.clone());
+        }
+    }
+
+    if emmy_new_debug_argument.ide_connect_debugger {
+        for endpoint in &emmy_new_debug_argument.targets {
+            let address = to_address(&endpoint.host, endpoint.port);
+            let target_endpoint = DebuggerEndpoint::Tcp(address.clone());
+            let mut target_conn = DebuggerConnection::new();
+            target_conn.set_request_timeout(request_timeout);
+            target_conn.set_max_message_size(max_message_size);
+            target_conn.set_closes_session(false);
+            target_conn.set_tls(connector.clone());
+            if let Err(e) = connect_with_retry(
+                &dap,
+                &mut target_conn,
+                &target_endpoint,
+                &emmy_new_debug_argument,
+                &cancel,
+            )
+            .await
+            {
+                log::error!("Failed to connect to target {}: {}", address, e);
+                send_console_output(&dap, format!("Failed to connect to {}: {}\n", address, e));
+                continue;
+            }
+
+            let disconnected = target_conn.watch_disconnect();
+            let name = endpoint.name.clone().unwrap_or(address.clone());
+            let Some(target) = dap.add_target(name, target_conn).await else {
+                log::error!("Too many debug targets, dropping {}", address);
+                send_console_output(
+                    &dap,
+                    format!("Too many debug targets, {} dropped\n", address),
+                );
+                break;
+            };
+            // started once the target is kept, dropping it then closes the link
+            target
+                .debugger_conn
+                .lock()
+                .await
+                .start_reader_task(dap.ide_conn.clone());
+            start_target_session(target.clone(), ext.clone());
+            if reconnect.enabled {
+                start_reconnect(
+                    target,
+                    disconnected,
+                    target_endpoint,
+                    reconnect.clone(),
+                    ext.clone(),
+                );
+            } else {
+                start_target_exit_watch(target, disconnected);
+            }
+        }
+    } else if let Some(listener) = listener.filter(|_| emmy_new_debug_argument.multiple_connections)
+    {
+        let dap = dap.clone();
+        tokio::spawn(async move {
+            loop {
+                let mut target_conn = DebuggerConnection::new();
+                target_conn.set_request_timeout(request_timeout);
+                target_conn.set_max_message_size(max_message_size);
+                target_conn.set_closes_session(false);
+                let peer_addr = match target_conn.accept(&listener).await {
+                    Ok(peer_addr) => peer_addr,
+                    Err(e) => {
+                        log::error!("Failed to accept debugger connection: {}", e);
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
 
-    let mut data = dap.data.lock().await;
-    data.extension = emmy_new_debug_argument.ext.clone();
-    data.sources = emmy_new_debug_argument.source_paths.clone();
+                log::info!("Debugger target connected from {}", peer_addr);
+                let disconnected = target_conn.watch_disconnect();
+                let Some(target) = dap.add_target(peer_addr.to_string(), target_conn).await else {
+                    log::error!("Too many debug targets, closing {}", peer_addr);
+                    send_console_output(
+                        &dap,
+                        format!("Too many debug targets, {} closed\n", peer_addr),
+                    );
+                    continue;
+                };
+                target
+                    .debugger_conn
+                    .lock()
+                    .await
+                    .start_reader_task(dap.ide_conn.clone());
+                {
+                    let mut ide_conn = dap.ide_conn.lock().unwrap();
+                    let _ = ide_conn.send_event(Event::Thread(ThreadEventBody {
+                        reason: ThreadEventReason::Started,
+                        thread_id: to_target_thread_id(target.target_id, MAIN_THREAD_ID),
+                    }));
+                }
+                start_target_exit_watch(target.clone(), disconnected);
+                start_target_session(target, ext.clone());
+            }
+        });
+    }
+
+    Ok(ResponseBody::Launch)
+}
+
+/// Keep trying until the debuggee opens its port or `connectTimeout` runs out, large
+/// games call `tcpListen` long after the editor started the session.
+async fn connect_with_retry(
+    dap: &DapSnapShot,
+    debugger_conn: &mut DebuggerConnection,
+    endpoint: &DebuggerEndpoint,
+    arguments: &EmmyNewDebugArguments,
+    cancel: &CancellationToken,
+) -> Result<(), RequestHandlerError> {
+    let window = Duration::from_millis(arguments.connect_timeout);
+    let interval = Duration::from_millis(arguments.connect_retry_interval);
+    let progress = Progress::start(dap, format!("Connecting to {}", endpoint), cancel);
+    let cancelled =
+        || RequestHandlerError::Message(format!("Connecting to {} was cancelled", endpoint));
+    let started = Instant::now();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        // the first attempt always runs, even with an empty window
+        let remaining = window.saturating_sub(started.elapsed());
+        let attempt_timeout = if remaining.is_zero() {
+            CONNECT_ATTEMPT_TIMEOUT
+        } else {
+            remaining.min(CONNECT_ATTEMPT_TIMEOUT)
+        };
+        let result = tokio::select! {
+            result = debugger_conn.connect(endpoint, Some(attempt_timeout)) => result,
+            _ = progress.cancel.cancelled() => return Err(cancelled()),
+        };
+        let err = match result {
+            Ok(_) => {
+                progress.end(format!("Connected to {}", endpoint));
+                return Ok(());
+            }
+            Err(err) => err,
+        };
+
+        let elapsed = started.elapsed();
+        // an inherited descriptor is taken over by the first attempt, also when it fails
+        if elapsed >= window || matches!(endpoint, DebuggerEndpoint::Fd(_)) {
+            progress.end(format!("Could not connect to {}", endpoint));
+            return Err(RequestHandlerError::Message(format!(
+                "Failed to connect to debugger after {} attempts: {}",
+                attempt, err
+            )));
+        }
+        log::info!("Connect attempt {} failed: {}", attempt, err);
+        progress.update(
+            format!(
+                "Waiting for the debuggee at {} ({}s)",
+                endpoint,
+                elapsed.as_secs()
+            ),
+            Some((elapsed.as_millis() * 100 / window.as_millis()) as i64),
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(interval.min(window - elapsed)) => {}
+            _ = progress.cancel.cancelled() => return Err(cancelled()),
+        }
+    }
+}
+
+fn compile_globs(option: &str, patterns: &[String]) -> Result<Vec<Pattern>, RequestHandlerError> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|e| {
+                RequestHandlerError::Message(format!("Invalid {} glob {}: {}", option, pattern, e))
+            })
+        })
+        .collect()
+}
+
+fn to_endpoint(arguments: &EmmyNewDebugArguments) -> Result<DebuggerEndpoint, RequestHandlerError> {
+    let path = || {
+        arguments.path.clone().ok_or_else(|| {
+            RequestHandlerError::Message(format!(
+                "The {:?} transport needs a `path`",
+                arguments.transport
+            ))
+        })
+    };
+    match arguments.transport {
+        TransportKind::Tcp => Ok(DebuggerEndpoint::Tcp(to_address(
+            &arguments.host,
+            arguments.port,
+        ))),
+        TransportKind::Unix => Ok(DebuggerEndpoint::Unix(path()?)),
+        TransportKind::Pipe => Ok(DebuggerEndpoint::Pipe(path()?)),
+        TransportKind::Fd => arguments.fd.map(DebuggerEndpoint::Fd).ok_or_else(|| {
+            RequestHandlerError::Message("The fd transport needs an `fd`".to_string())
+        }),
+    }
+}
+
+/// `host:port`, ipv6 literals get their brackets. Host names are resolved on connect.
+fn to_address(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
 
-    let dap = dap.clone();
-    let ide_conn = dap.ide_conn.clone();
+/// Wait for the debuggee to settle, then hook notifications and send the initial state.
+fn start_target_session(dap: DapSnapShot, ext: Vec<String>) {
     tokio::spawn(async move {
         tokio::time::sleep(std::time::Duration::from_millis(200)).await;
         log::info!("Registering debugger notification");
         register_debugger_notification(dap.clone()).await;
 
         log::info!("after debugger connected");
-        match after_debugger_connected(dap, emmy_new_debug_argument.ext).await {
+        let is_primary = dap.target_id == 0;
+        let ide_conn = dap.ide_conn.clone();
+        match after_debugger_connected(dap, ext).await {
             Ok(_) => {
                 log::info!("Debugger connection fully established");
             }
-            Err(err) => {
+            Err(err) if is_primary => {
                 log::error!("Failed to handle debugger connected: {}", err);
                 // Send a terminated event to notify the client
                 let mut output = ide_conn.lock().unwrap();
-                let _ = output.send_event(dap::events::Event::Terminated(None));
+                let _ = output.send_event(Event::Terminated(None));
                 // Don't exit - let the client decide what to do
             }
+            Err(err) => {
+                log::error!("Failed to set up debug target: {}", err);
+            }
         }
     });
+}
 
-    Ok(ResponseBody::Launch)
+pub fn send_console_output(dap: &DapSnapShot, output: String) {
+    let mut ide_conn = dap.ide_conn.lock().unwrap();
+    let _ = ide_conn.send_event(Event::Output(OutputEventBody {
+        category: Some(dap::types::OutputEventCategory::Console),
+        output,
+        ..Default::default()
+    }));
 }
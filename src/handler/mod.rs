@@ -1,28 +1,42 @@
+mod completions_request;
+mod console_command;
+mod coroutine_threads;
 mod debug_action_request;
 mod debugger_connected;
 mod debugger_notification;
 mod evaluate_request;
+mod goto_request;
 mod initialize_request;
 mod launch_request;
+mod progress;
+mod reconnect;
+mod restart_frame_request;
 mod scopes_request;
 mod set_breakpoint_request;
 mod stack_trace_request;
+mod step_back_request;
+mod step_in_targets_request;
 mod threads_request;
 mod variables_request;
 
 use std::error::Error;
 
+use completions_request::on_completions_request;
 use dap::{
     errors::ServerError,
     requests::{Command, Request},
     responses::{ResponseBody, SetExceptionBreakpointsResponse},
 };
 use evaluate_request::on_evaluate_request;
+use goto_request::{on_goto_request, on_goto_targets_request};
 pub use initialize_request::on_initialize_request;
 pub use launch_request::on_launch_request;
+use restart_frame_request::on_restart_frame_request;
 use scopes_request::on_scopes_request;
 use set_breakpoint_request::on_set_breakpoints_request;
 use stack_trace_request::on_stack_trace_request;
+use step_back_request::{on_reverse_continue_request, on_step_back_request};
+use step_in_targets_request::on_step_in_targets_request;
 use threads_request::on_threads_request;
 use variables_request::on_variable_request;
 
@@ -68,30 +82,92 @@ pub async fn on_request_dispatch(
                 .task(request, evaluate_argument, on_evaluate_request)
                 .await;
         }
-        Command::Pause(_) => {
+        Command::Completions(completions_argument) => {
             context
-                .task(request, (), debug_action_request::on_pause_request)
+                .task(request, completions_argument, on_completions_request)
                 .await;
         }
-        Command::Continue(_) => {
+        Command::Pause(pause_argument) => {
             context
-                .task(request, (), debug_action_request::on_continue_request)
+                .task(
+                    request,
+                    pause_argument,
+                    debug_action_request::on_pause_request,
+                )
                 .await;
         }
-        Command::StepIn(_) => {
+        Command::Continue(continue_argument) => {
             context
-                .task(request, (), debug_action_request::on_step_in_request)
+                .task(
+                    request,
+                    continue_argument,
+                    debug_action_request::on_continue_request,
+                )
+                .await;
+        }
+        Command::StepIn(step_in_argument) => {
+            context
+                .task(
+                    request,
+                    step_in_argument,
+                    debug_action_request::on_step_in_request,
+                )
                 .await;
         }
 
-        Command::StepOut(_) => {
+        Command::StepInTargets(step_in_targets_argument) => {
+            context
+                .task_as(
+                    request,
+                    "stepInTargets",
+                    step_in_targets_argument,
+                    on_step_in_targets_request,
+                )
+                .await;
+        }
+        Command::StepOut(step_out_argument) => {
+            context
+                .task(
+                    request,
+                    step_out_argument,
+                    debug_action_request::on_step_out_request,
+                )
+                .await;
+        }
+        Command::Next(next_argument) => {
+            context
+                .task(
+                    request,
+                    next_argument,
+                    debug_action_request::on_next_request,
+                )
+                .await;
+        }
+        Command::StepBack(step_back_argument) => {
+            context
+                .task(request, step_back_argument, on_step_back_request)
+                .await;
+        }
+        Command::ReverseContinue(reverse_continue_argument) => {
             context
-                .task(request, (), debug_action_request::on_step_out_request)
+                .task(
+                    request,
+                    reverse_continue_argument,
+                    on_reverse_continue_request,
+                )
                 .await;
         }
-        Command::Next(_) => {
+        Command::GotoTargets(goto_targets_argument) => {
             context
-                .task(request, (), debug_action_request::on_next_request)
+                .task(request, goto_targets_argument, on_goto_targets_request)
+                .await;
+        }
+        Command::Goto(goto_argument) => {
+            context.task(request, goto_argument, on_goto_request).await;
+        }
+        Command::RestartFrame(restart_frame_argument) => {
+            context
+                .task(request, restart_frame_argument, on_restart_frame_request)
                 .await;
         }
         Command::SetBreakpoints(set_breakpoint_argument) => {
@@ -103,6 +179,9 @@ pub async fn on_request_dispatch(
             if let Some(req_id) = cancel_argument.request_id {
                 context.cancel(req_id).await;
             }
+            if let Some(progress_id) = &cancel_argument.progress_id {
+                context.cancel_progress(progress_id);
+            }
 
             return Ok(());
         }
@@ -0,0 +1,69 @@
+use dap::events::{Event, ProgressEndEventBody, ProgressStartEventBody, ProgressUpdateEventBody};
+use tokio_util::sync::CancellationToken;
+
+use crate::context::DapSnapShot;
+
+/// A long running operation shown in the editor. Cancelling the progress or the request
+/// it belongs to triggers `cancel`, the progress ends when dropped.
+pub struct Progress {
+    dap: DapSnapShot,
+    progress_id: String,
+    enabled: bool,
+    end_message: Option<String>,
+    pub cancel: CancellationToken,
+}
+
+impl Progress {
+    pub fn start(dap: &DapSnapShot, title: String, request_cancel: &CancellationToken) -> Self {
+        let cancel = request_cancel.child_token();
+        let (progress_id, enabled) = {
+            let mut progress = dap.progress.lock().unwrap();
+            (progress.start(cancel.clone()), progress.enabled)
+        };
+        let progress = Progress {
+            dap: dap.clone(),
+            progress_id,
+            enabled,
+            end_message: None,
+            cancel,
+        };
+        progress.send(Event::ProgressStart(ProgressStartEventBody {
+            progress_id: progress.progress_id.clone(),
+            title,
+            cancellable: Some(true),
+            ..Default::default()
+        }));
+        progress
+    }
+
+    pub fn update(&self, message: String, percentage: Option<i64>) {
+        self.send(Event::ProgressUpdate(ProgressUpdateEventBody {
+            progress_id: self.progress_id.clone(),
+            message: Some(message),
+            percentage,
+        }));
+    }
+
+    pub fn end(mut self, message: String) {
+        self.end_message = Some(message);
+    }
+
+    fn send(&self, event: Event) {
+        // editors without progress support get nothing, the operation runs all the same
+        if self.enabled {
+            let mut ide_conn = self.dap.ide_conn.lock().unwrap();
+            let _ = ide_conn.send_event(event);
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.dap.progress.lock().unwrap().end(&self.progress_id);
+        let message = self.end_message.take();
+        self.send(Event::ProgressEnd(ProgressEndEventBody {
+            progress_id: self.progress_id.clone(),
+            message,
+        }));
+    }
+}
@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use dap::{
+    events::{ContinuedEventBody, Event, ThreadEventBody},
+    types::ThreadEventReason,
+};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsConnector;
+
+use crate::{
+    context::{
+        ACCEPT_RETRY_DELAY, CONNECT_ATTEMPT_TIMEOUT, DapSnapShot, DebuggerConnection,
+        DebuggerEndpoint, DebuggerListener, MAIN_THREAD_ID, ReconnectOptions, to_target_thread_id,
+    },
+    handler::{debugger_connected::send_initial_state, launch_request::send_console_output},
+};
+
+/// Keep a target across dropped links, for debuggees that restart or reload. Once
+/// linked again the debuggee gets the same setup as on the first connection.
+pub fn start_reconnect(
+    dap: DapSnapShot,
+    mut disconnected: mpsc::Receiver<()>,
+    endpoint: DebuggerEndpoint,
+    options: ReconnectOptions,
+    ext: Vec<String>,
+) {
+    tokio::spawn(async move {
+        let tls = dap.debugger_conn.lock().await.tls();
+        while disconnected.recv().await.is_some() {
+            reset_stop_state(&dap).await;
+            send_console_output(&dap, "Reconnecting…\n".to_string());
+
+            let Some(link) = relink(&endpoint, tls.clone(), &options).await else {
+                log::error!("Giving up reconnecting to {}", dap.target_name());
+                send_console_output(
+                    &dap,
+                    format!("Reconnect failed after {} attempts\n", options.max_attempts),
+                );
+                if dap.target_id == 0 {
+                    let mut ide_conn = dap.ide_conn.lock().unwrap();
+                    let _ = ide_conn.send_event(Event::Terminated(None));
+                } else {
+                    remove_target(&dap).await;
+                }
+                break;
+            };
+
+            {
+                let mut debugger_conn = dap.debugger_conn.lock().await;
+                debugger_conn.reattach(link);
+                debugger_conn.start_reader_task(dap.ide_conn.clone());
+            }
+            // a failure here drops the link again, which starts the next round
+            if let Err(err) = send_initial_state(&dap, ext.clone()).await {
+                log::error!("Failed to set up reconnected debugger: {}", err);
+                continue;
+            }
+            send_console_output(&dap, "Reconnected\n".to_string());
+        }
+    });
+}
+
+async fn relink(
+    endpoint: &DebuggerEndpoint,
+    tls: Option<TlsConnector>,
+    options: &ReconnectOptions,
+) -> Option<DebuggerConnection> {
+    let interval = Duration::from_millis(options.interval_ms);
+    let mut attempt = 0;
+    while options.max_attempts == 0 || attempt < options.max_attempts {
+        attempt += 1;
+        tokio::time::sleep(interval).await;
+        let mut link = DebuggerConnection::new();
+        link.set_tls(tls.clone());
+        match link.connect(endpoint, Some(CONNECT_ATTEMPT_TIMEOUT)).await {
+            Ok(_) => return Some(link),
+            Err(err) => log::info!("Reconnect attempt {} failed: {}", attempt, err),
+        }
+    }
+    None
+}
+
+/// Listen mode with reconnect keeps the listener for the whole session. A debuggee that
+/// restarts connects again and takes over the target, so does any other debuggee
+/// connecting while one is still attached.
+pub fn start_relisten(
+    dap: DapSnapShot,
+    mut disconnected: mpsc::Receiver<()>,
+    listener: DebuggerListener,
+    ext: Vec<String>,
+) {
+    tokio::spawn(async move {
+        let mut attached = true;
+        loop {
+            let mut link = DebuggerConnection::new();
+            tokio::select! {
+                accepted = link.accept(&listener) => {
+                    let peer = match accepted {
+                        Ok(peer) => peer,
+                        Err(err) => {
+                            log::error!("Failed to accept debugger connection: {}", err);
+                            tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                            continue;
+                        }
+                    };
+                    log::info!("Debugger connected from {}", peer);
+                    reset_stop_state(&dap).await;
+                    {
+                        let mut debugger_conn = dap.debugger_conn.lock().await;
+                        debugger_conn.reattach(link);
+                        debugger_conn.start_reader_task(dap.ide_conn.clone());
+                    }
+                    // the replaced link may have reported its end already
+                    while disconnected.try_recv().is_ok() {}
+                    let output = if attached {
+                        format!("Debuggee connected from {}, replacing the previous connection\n", peer)
+                    } else {
+                        format!("Debuggee connected from {}\n", peer)
+                    };
+                    send_console_output(&dap, output);
+                    attached = true;
+                    if let Err(err) = send_initial_state(&dap, ext.clone()).await {
+                        log::error!("Failed to set up debugger connected from {}: {}", peer, err);
+                    }
+                }
+                Some(()) = disconnected.recv() => {
+                    attached = false;
+                    reset_stop_state(&dap).await;
+                    send_console_output(
+                        &dap,
+                        format!("Waiting for the debuggee to connect to {}\n", dap.target_name()),
+                    );
+                }
+            }
+        }
+    });
+}
+
+/// Extra targets without reconnect leave the session together with their debuggee.
+pub fn start_target_exit_watch(dap: DapSnapShot, mut disconnected: mpsc::Receiver<()>) {
+    tokio::spawn(async move {
+        if disconnected.recv().await.is_some() {
+            remove_target(&dap).await;
+        }
+    });
+}
+
+/// Drop an extra target and tell the editor its threads are gone.
+async fn remove_target(dap: &DapSnapShot) {
+    let mut thread_ids = vec![MAIN_THREAD_ID];
+    thread_ids.extend(
+        dap.data
+            .lock()
+            .await
+            .coroutines
+            .iter()
+            .map(|co| co.thread_id),
+    );
+    let name = dap.target_name();
+    if !dap.remove_target() {
+        return;
+    }
+    log::info!("Debug target {} removed", name);
+
+    let mut ide_conn = dap.ide_conn.lock().unwrap();
+    for thread_id in thread_ids {
+        let _ = ide_conn.send_event(Event::Thread(ThreadEventBody {
+            reason: ThreadEventReason::Exited,
+            thread_id: to_target_thread_id(dap.target_id, thread_id),
+        }));
+    }
+}
+
+/// Whatever the lost debuggee was doing is gone, a stop shown in the editor included.
+async fn reset_stop_state(dap: &DapSnapShot) {
+    let stopped_thread_id = {
+        let mut data = dap.data.lock().await;
+        let was_stopped = data.is_stopped();
+        data.resume();
+        data.coroutine_tracker_installed = false;
+        data.coroutine_ids.clear();
+        data.step_action = None;
+        data.step_in_goal = None;
+        data.run_to_breakpoint = None;
+        was_stopped.then_some(data.stopped_thread_id)
+    };
+
+    if let Some(thread_id) = stopped_thread_id {
+        let mut ide_conn = dap.ide_conn.lock().unwrap();
+        let _ = ide_conn.send_event(Event::Continued(ContinuedEventBody {
+            thread_id: to_target_thread_id(dap.target_id, thread_id),
+            all_threads_continued: Some(dap.target_count() == 1),
+        }));
+    }
+}
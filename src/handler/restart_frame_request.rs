@@ -0,0 +1,74 @@
+use dap::requests::RestartFrameArguments;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    context::DapSnapShot,
+    handler::{RequestHandlerError, stack_trace_request::StackInfo},
+};
+
+use super::RequestResult;
+
+/// Lua can not rewind a frame, and calling the function again from its caller would run
+/// its side effects twice and outside of the debug hooks. Every frame is refused, with
+/// the reason that applies to it.
+pub async fn on_restart_frame_request(
+    dap: DapSnapShot,
+    restart_frame_arguments: RestartFrameArguments,
+    _: CancellationToken,
+) -> RequestResult {
+    log::info!(
+        "Received RestartFrame request: {:#?}",
+        restart_frame_arguments
+    );
+    let Some((dap, Some(frame_id))) = dap.frame_target(Some(restart_frame_arguments.frame_id))
+    else {
+        return Err(RequestHandlerError::Message(format!(
+            "Invalid frame id: {}",
+            restart_frame_arguments.frame_id
+        ))
+        .into());
+    };
+
+    let data = dap.data.lock().await;
+    let Some(stack) = data.get_stack(frame_id) else {
+        return Err(RequestHandlerError::Message(format!(
+            "Invalid frame id: {}",
+            restart_frame_arguments.frame_id
+        ))
+        .into());
+    };
+    Err(RequestHandlerError::Message(refusal(&StackInfo::from(stack)).to_string()).into())
+}
+
+fn refusal(stack: &StackInfo) -> &'static str {
+    if stack.is_tail_call() {
+        "Frames lost to tail calls can not be restarted"
+    } else if stack.is_native() {
+        "C functions can not be restarted"
+    } else {
+        "Lua frames can not be restarted: the call would run again from its caller, \
+         repeating its side effects and without stopping at breakpoints"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(file: &str, line: i32) -> StackInfo {
+        StackInfo {
+            level: 0,
+            function_name: "f".to_string(),
+            file: file.to_string(),
+            line,
+            callee: None,
+        }
+    }
+
+    #[test]
+    fn names_the_reason() {
+        assert!(refusal(&frame("=(tail call)", -1)).contains("tail calls"));
+        assert!(refusal(&frame("=[C]", -1)).contains("C functions"));
+        assert!(refusal(&frame("@main.lua", 3)).contains("side effects"));
+    }
+}
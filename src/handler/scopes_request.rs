@@ -1,7 +1,10 @@
 use dap::{requests::ScopesArguments, responses::ResponseBody};
 use tokio_util::sync::CancellationToken;
 
-use crate::context::{DapSnapShot, DebuggerCacheItem, DebuggerCacheRef};
+use crate::{
+    context::{DapSnapShot, DebuggerCacheItem, DebuggerCacheRef},
+    handler::RequestHandlerError,
+};
 
 use super::RequestResult;
 
@@ -11,10 +14,17 @@ pub async fn on_scopes_request(
     _: CancellationToken,
 ) -> RequestResult {
     log::info!("Received Scopes request: {:#?}", scopes_arguments);
+    let Some((dap, Some(frame_id))) = dap.frame_target(Some(scopes_arguments.frame_id)) else {
+        return Err(RequestHandlerError::Message(format!(
+            "Invalid frame id: {}",
+            scopes_arguments.frame_id
+        ))
+        .into());
+    };
     let mut data = dap.data.lock().await;
-    data.current_frame_id = scopes_arguments.frame_id;
+    data.current_frame_id = frame_id;
     let mut scopes = vec![];
-    if let Some(stack) = data.stacks.get(scopes_arguments.frame_id as usize).cloned() {
+    if let Some(stack) = data.get_stack(frame_id).cloned() {
         let ref_id = data.cache.allocate_cache_id();
         let stack_item =
             DebuggerCacheItem::Stack(DebuggerCacheRef::new(ref_id, stack.clone()).into());
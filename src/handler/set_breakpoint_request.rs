@@ -4,7 +4,10 @@ use dap::{
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::context::{AddBreakPointReq, BreakPoint, DapSnapShot, Message, MessageCMD};
+use crate::{
+    context::{AddBreakPointReq, BreakPoint, DapSnapShot, DebuggerData, Message, MessageCMD},
+    handler::stack_trace_request::source_line,
+};
 
 use super::RequestResult;
 
@@ -20,33 +23,85 @@ pub async fn on_set_breakpoints_request(
     let source = set_breakpoints_arguments.source;
     let mut response_breakpoints = vec![];
     if let Some(path) = source.path {
-        let mut data = dap.data.lock().await;
-        data.breakpoints.retain(|key, _| key.0 != path);
-        if let Some(breakpoints) = set_breakpoints_arguments.breakpoints {
-            for breakpoint in breakpoints {
-                let line = breakpoint.line;
-                let key = (path.clone(), line);
-                let debugger_point = BreakPoint {
-                    file: path.clone(),
-                    line: line as i32,
-                    condition: breakpoint.condition.clone(),
-                    hit_condition: breakpoint.hit_condition.clone(),
-                    log_message: breakpoint.log_message.clone(),
-                };
-                data.breakpoints.insert(key, debugger_point);
-                let id = data.breakpoint_id;
-                data.breakpoint_id += 1;
-                let response_breakpoint = dap::types::Breakpoint {
-                    verified: true,
-                    id: Some(id),
-                    line: Some(line),
-                    ..Default::default()
-                };
+        let mut file_breakpoints = vec![];
+        let mut file_columns: Vec<((String, i64), i64)> = vec![];
+        {
+            let client = *dap.client.lock().unwrap();
+            let mut data = dap.data.lock().await;
+            // breakpoints are set again after the file was saved
+            data.source_cache.remove(&path);
+            if let Some(breakpoints) = set_breakpoints_arguments.breakpoints {
+                for breakpoint in breakpoints {
+                    let line = client.adapter_line(breakpoint.line);
+                    // the debuggee keeps one breakpoint per line, a second one would take
+                    // over the condition of the first
+                    if file_breakpoints
+                        .iter()
+                        .any(|((_, taken), _)| *taken == line)
+                    {
+                        response_breakpoints.push(dap::types::Breakpoint {
+                            verified: false,
+                            message: Some(format!(
+                                "Line {} already has a breakpoint, the debuggee breaks by line",
+                                line
+                            )),
+                            line: Some(breakpoint.line),
+                            column: breakpoint.column,
+                            ..Default::default()
+                        });
+                        continue;
+                    }
+                    // inline breakpoints break on their line, the column is kept adapter side
+                    // and only accepted where the line has code
+                    if let Some(client_column) = breakpoint.column {
+                        let column = client.adapter_column(client_column);
+                        if !has_code_at(&mut data, &path, line, column) {
+                            response_breakpoints.push(dap::types::Breakpoint {
+                                verified: false,
+                                message: Some(format!(
+                                    "No code at line {}, column {}",
+                                    line, column
+                                )),
+                                line: Some(breakpoint.line),
+                                column: Some(client_column),
+                                ..Default::default()
+                            });
+                            continue;
+                        }
+                        file_columns.push(((path.clone(), line), column));
+                    }
+
+                    let debugger_point = BreakPoint {
+                        file: path.clone(),
+                        line: line as i32,
+                        condition: breakpoint.condition.clone(),
+                        hit_condition: breakpoint.hit_condition.clone(),
+                        log_message: breakpoint.log_message.clone(),
+                    };
+                    file_breakpoints.push(((path.clone(), line), debugger_point));
+                    let id = data.breakpoint_id;
+                    data.breakpoint_id += 1;
+                    let response_breakpoint = dap::types::Breakpoint {
+                        verified: true,
+                        id: Some(id),
+                        line: Some(breakpoint.line),
+                        column: breakpoint.column,
+                        ..Default::default()
+                    };
 
-                response_breakpoints.push(response_breakpoint);
+                    response_breakpoints.push(response_breakpoint);
+                }
             }
         }
-        drop(data);
+
+        // every lua state of the session shares the same breakpoints
+        for target in dap.all_targets() {
+            let mut data = target.data.lock().await;
+            data.breakpoints.retain(|key, _| key.0 != path);
+            data.breakpoints.extend(file_breakpoints.iter().cloned());
+            data.breakpoint_columns.retain(|key, _| key.0 != path);
+            data.breakpoint_columns.extend(file_columns.iter().cloned());
+        }
         send_all_breakpoints(dap.clone()).await;
     } else {
         log::error!("No path provided in source");
@@ -57,11 +112,31 @@ pub async fn on_set_breakpoints_request(
     }))
 }
 
+fn has_code_at(data: &mut DebuggerData, path: &str, line: i64, column: i64) -> bool {
+    let Some(line_text) = source_line(data, path, line as i32) else {
+        // the file can not be read, trust the editor
+        return true;
+    };
+    let skip = usize::try_from(column - 1).unwrap_or(0);
+    line_text.chars().skip(skip).any(|c| !c.is_whitespace())
+}
+
 pub async fn send_all_breakpoints(dap: DapSnapShot) {
+    for target in dap.all_targets() {
+        send_target_breakpoints(target).await;
+    }
+}
+
+/// The user's breakpoints plus the one-shot breakpoint of a pending run to line.
+pub async fn send_target_breakpoints(dap: DapSnapShot) {
     let data = dap.data.lock().await;
-    let breakpoints = data.breakpoints.values().cloned().collect::<Vec<_>>();
+    let mut breakpoints = data.breakpoints.values().cloned().collect::<Vec<_>>();
+    breakpoints.extend(data.run_to_breakpoint.clone());
     log::info!("send all breakpoint: {:#?}", breakpoints);
     let debugger_conn = dap.debugger_conn.lock().await;
+    if !debugger_conn.is_connected() {
+        return;
+    }
     match debugger_conn
         .send_message(Message::AddBreakPointReq(AddBreakPointReq {
             cmd: MessageCMD::AddBreakPointReq as i64,
@@ -72,7 +147,11 @@ pub async fn send_all_breakpoints(dap: DapSnapShot) {
     {
         Ok(_) => {}
         Err(err) => {
-            log::error!("Failed to send breakpoints: {}", err);
+            log::error!(
+                "Failed to send breakpoints to {}: {}",
+                dap.target_name(),
+                err
+            );
         }
     }
 }
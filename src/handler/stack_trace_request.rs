@@ -1,44 +1,138 @@
 use std::path::Path;
 
-use dap::{requests::StackTraceArguments, responses::ResponseBody, types::Source};
+use dap::{
+    requests::StackTraceArguments,
+    responses::ResponseBody,
+    types::{Source, StackFramePresentationhint},
+};
+use glob::Pattern;
 use tokio_util::sync::CancellationToken;
 
-use crate::context::{DapSnapShot, DebuggerData, Stack};
+use crate::{
+    context::{
+        DapSnapShot, DebuggerData, Stack, chunk_matches, thread_frame_id, to_target_frame_id,
+    },
+    handler::{RequestHandlerError, coroutine_threads::fetch_coroutine_stacks},
+    lua_syntax::call_expressions,
+};
 
 use super::RequestResult;
 
 pub async fn on_stack_trace_request(
     dap: DapSnapShot,
-    _: StackTraceArguments,
+    stack_trace_arguments: StackTraceArguments,
     _: CancellationToken,
 ) -> RequestResult {
+    let Some((dap, thread_id)) = dap.thread_target(stack_trace_arguments.thread_id) else {
+        return Err(RequestHandlerError::Message(format!(
+            "Unknown thread {}",
+            stack_trace_arguments.thread_id
+        ))
+        .into());
+    };
+    let (is_other_thread, is_replaying) = {
+        let data = dap.data.lock().await;
+        (
+            data.track_coroutines && data.is_stopped() && thread_id != data.stopped_thread_id,
+            data.history.is_replaying(),
+        )
+    };
+    // a replayed stop only recorded the thread that hit the break
+    if is_other_thread && is_replaying {
+        return Ok(ResponseBody::StackTrace(
+            dap::responses::StackTraceResponse {
+                stack_frames: vec![],
+                total_frames: Some(0),
+            },
+        ));
+    }
+    // stacks of other coroutines are fetched on demand, their frame ids carry the thread id
+    if is_other_thread {
+        let stacks = fetch_coroutine_stacks(&dap, thread_id).await;
+        dap.data
+            .lock()
+            .await
+            .thread_stacks
+            .insert(thread_id, stacks);
+    }
+
+    let client = *dap.client.lock().unwrap();
     let mut data = dap.data.lock().await;
 
-    let mut stack_frames = vec![];
-    let stacks = data.stacks.iter().map(StackInfo::from).collect::<Vec<_>>();
-    for stack in stacks {
-        let file_string = stack.file.clone();
-        if stack.line <= 0 {
-            continue;
-        }
+    let stacks = if is_other_thread {
+        data.thread_stacks
+            .get(&thread_id)
+            .map(|stacks| stacks.iter().map(StackInfo::from).collect::<Vec<_>>())
+            .unwrap_or_default()
+    } else {
+        data.stacks.iter().map(StackInfo::from).collect::<Vec<_>>()
+    };
+    // the frame above a caller tells which call of the caller's line is running
+    let mut stacks = stacks;
+    for i in 1..stacks.len() {
+        stacks[i].callee = Some(stacks[i - 1].function_name.clone());
+    }
+    let entries = collapse_library_frames(stacks, &data.library_files);
 
-        // todo find real file
-        let file_path = find_file_path(&mut data, file_string.clone()).await?;
-        let stack_frame = dap::types::StackFrame {
-            id: stack.level as i64,
-            name: stack.function_name.clone(),
-            source: Some(Source {
-                name: Some(file_string),
-                path: file_path,
-                ..Default::default()
-            }),
-            line: stack.line as i64,
+    // the whole list is cheap to build, only the requested page resolves source paths
+    let total_frames = entries.len() as i64;
+    let start_frame = stack_trace_arguments.start_frame.unwrap_or(0).max(0) as usize;
+    let levels = match stack_trace_arguments.levels {
+        Some(levels) if levels > 0 => levels as usize,
+        _ => entries.len(),
+    };
+
+    let mut stack_frames = vec![];
+    for entry in entries.into_iter().skip(start_frame).take(levels) {
+        let (stack, name, hint) = match entry {
+            FrameEntry::Frame(stack) => {
+                let name = stack.function_name.clone();
+                (stack, name, None)
+            }
+            FrameEntry::Label(stack, count) => {
+                let name = format!("[{} library frames]", count);
+                (stack, name, Some(StackFramePresentationhint::Label))
+            }
+        };
+        let frame_id = if is_other_thread {
+            thread_frame_id(thread_id, stack.level as i64)
+        } else {
+            stack.level as i64
+        };
+        let mut stack_frame = dap::types::StackFrame {
+            id: to_target_frame_id(dap.target_id, frame_id),
+            name,
+            presentation_hint: hint,
             ..Default::default()
         };
+
+        if stack.is_tail_call() {
+            stack_frame.name = "(...tail calls...)".to_string();
+            stack_frame.presentation_hint = Some(StackFramePresentationhint::Label);
+        } else if stack.is_native() {
+            // c functions have no source, keep them visible but out of the way
+            stack_frame.presentation_hint = Some(StackFramePresentationhint::Subtle);
+        } else if stack_frame.presentation_hint.is_none() {
+            let file_path = find_file_path(&mut data, stack.file.clone()).await?;
+            stack_frame.source = Some(Source {
+                name: Some(stack.file.clone()),
+                path: file_path.clone(),
+                ..Default::default()
+            });
+            stack_frame.line = client.client_line(stack.line as i64);
+            if let Some(path) = &file_path {
+                let breakpoint_column = if is_other_thread || stack.level != 0 {
+                    None
+                } else {
+                    data.stop_column
+                };
+                let column = frame_column(&mut data, path, &stack, breakpoint_column);
+                stack_frame.column = client.client_column(column);
+            }
+        }
         stack_frames.push(stack_frame);
     }
 
-    let total_frames = stack_frames.len() as i64;
     Ok(ResponseBody::StackTrace(
         dap::responses::StackTraceResponse {
             stack_frames,
@@ -47,11 +141,46 @@ pub async fn on_stack_trace_request(
     ))
 }
 
+enum FrameEntry {
+    Frame(StackInfo),
+    /// a run of library frames, represented by the first one
+    Label(StackInfo, usize),
+}
+
+/// Runs of two or more frames from `libraryFiles` are folded into one label frame,
+/// the frame that hit the break is always shown.
+fn collapse_library_frames(stacks: Vec<StackInfo>, library_files: &[Pattern]) -> Vec<FrameEntry> {
+    let mut entries = vec![];
+    let mut run: Vec<StackInfo> = vec![];
+    let flush = |run: &mut Vec<StackInfo>, entries: &mut Vec<FrameEntry>| {
+        if run.len() >= 2 {
+            let count = run.len();
+            entries.push(FrameEntry::Label(run.remove(0), count));
+            run.clear();
+        } else {
+            entries.extend(run.drain(..).map(FrameEntry::Frame));
+        }
+    };
+
+    for (index, stack) in stacks.into_iter().enumerate() {
+        if index > 0 && stack.is_library(library_files) {
+            run.push(stack);
+        } else {
+            flush(&mut run, &mut entries);
+            entries.push(FrameEntry::Frame(stack));
+        }
+    }
+    flush(&mut run, &mut entries);
+    entries
+}
+
 pub struct StackInfo {
     pub level: i32,
     pub function_name: String,
     pub file: String,
     pub line: i32,
+    /// function called from this frame, none for the top frame
+    pub callee: Option<String>,
 }
 
 impl StackInfo {
@@ -61,11 +190,71 @@ impl StackInfo {
             function_name: stack.function_name.clone(),
             file: stack.file.clone(),
             line: stack.line,
+            callee: None,
+        }
+    }
+
+    /// c functions report no line
+    pub fn is_native(&self) -> bool {
+        self.line <= 0
+    }
+
+    /// lua 5.1 reports the frames lost to tail calls as one `(tail call)` frame
+    pub fn is_tail_call(&self) -> bool {
+        self.file == "=(tail call)"
+    }
+
+    pub fn is_library(&self, library_files: &[Pattern]) -> bool {
+        if self.is_native() || self.is_tail_call() {
+            return false;
         }
+        chunk_matches(&self.file, library_files)
+    }
+}
+
+/// Best effort column of a frame, 1 based: the top frame points at the inline breakpoint
+/// it stopped at or the first token of the line, callers point at the call of the frame
+/// above.
+fn frame_column(
+    data: &mut DebuggerData,
+    path: &str,
+    stack: &StackInfo,
+    breakpoint_column: Option<i64>,
+) -> i64 {
+    if let Some(column) = breakpoint_column {
+        return column;
+    }
+    let Some(line_text) = source_line(data, path, stack.line) else {
+        return 1;
+    };
+
+    let to_column = |offset: usize| line_text[..offset].chars().count() as i64 + 1;
+    if let Some(callee) = &stack.callee {
+        let call = call_expressions(&line_text)
+            .into_iter()
+            .find(|call| call.function_name() == callee);
+        if let Some(call) = call {
+            return to_column(call.start);
+        }
+    }
+
+    let indent = line_text.len() - line_text.trim_start().len();
+    to_column(indent)
+}
+
+/// Line of a resolved source file, 1 based. Files are read once and kept.
+pub fn source_line(data: &mut DebuggerData, path: &str, line: i32) -> Option<String> {
+    let index = usize::try_from(line - 1).ok()?;
+    if !data.source_cache.contains_key(path) {
+        let lines = std::fs::read_to_string(path)
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        data.source_cache.insert(path.to_string(), lines);
     }
+    data.source_cache.get(path)?.get(index).cloned()
 }
 
-async fn find_file_path(
+pub async fn find_file_path(
     data: &mut DebuggerData,
     chunkname: String,
 ) -> Result<Option<String>, Box<dyn std::error::Error + Send>> {
@@ -0,0 +1,204 @@
+use dap::{
+    events::{Event, StoppedEventBody},
+    requests::{ReverseContinueArguments, StepBackArguments},
+    responses::ResponseBody,
+    types::StoppedEventReason,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    context::{DapSnapShot, StopSnapshot, ValueType, to_target_thread_id},
+    handler::RequestHandlerError,
+};
+
+use super::RequestResult;
+
+pub async fn on_step_back_request(
+    dap: DapSnapShot,
+    step_back_arguments: StepBackArguments,
+    _: CancellationToken,
+) -> RequestResult {
+    log::info!("Received StepBack request");
+    replay_back(&dap, step_back_arguments.thread_id, false).await?;
+
+    Ok(ResponseBody::StepBack)
+}
+
+pub async fn on_reverse_continue_request(
+    dap: DapSnapShot,
+    reverse_continue_arguments: ReverseContinueArguments,
+    _: CancellationToken,
+) -> RequestResult {
+    log::info!("Received ReverseContinue request");
+    replay_back(&dap, reverse_continue_arguments.thread_id, true).await?;
+
+    Ok(ResponseBody::ReverseContinue)
+}
+
+async fn replay_back(
+    dap: &DapSnapShot,
+    thread_id: i64,
+    to_breakpoint: bool,
+) -> Result<(), Box<dyn std::error::Error + Send>> {
+    let Some((dap, _)) = dap.thread_target(thread_id) else {
+        return Err(RequestHandlerError::Message(format!("Unknown thread {}", thread_id)).into());
+    };
+    let mut data = dap.data.lock().await;
+    if !data.history.is_enabled() {
+        return Err(RequestHandlerError::Message(
+            "Stop history is off, set `history` in the launch configuration".to_string(),
+        )
+        .into());
+    }
+    let Some(snapshot) = data.history.back(to_breakpoint).cloned() else {
+        return Err(
+            RequestHandlerError::Message("No earlier stop was recorded".to_string()).into(),
+        );
+    };
+
+    let reason = if snapshot.by_step {
+        StoppedEventReason::Step
+    } else {
+        StoppedEventReason::String("breakpoint".to_string())
+    };
+    let description = replay_description(data.history.replay_index(), data.history.stop_count());
+    show_snapshot(&dap, &mut data, snapshot, reason, description);
+    Ok(())
+}
+
+/// Step forward through the recorded stops, `to_live` jumps back to the live stop.
+/// Returns false when no recorded stop is being replayed, the action then goes to the
+/// debuggee.
+pub async fn replay_forward(dap: &DapSnapShot, to_live: bool) -> bool {
+    let mut data = dap.data.lock().await;
+    let Some(snapshot) = data.history.forward(to_live).cloned() else {
+        return false;
+    };
+
+    let description = replay_description(data.history.replay_index(), data.history.stop_count());
+    show_snapshot(
+        dap,
+        &mut data,
+        snapshot,
+        StoppedEventReason::Step,
+        description,
+    );
+    true
+}
+
+/// Error for requests that need the live debuggee while a recorded stop is shown.
+pub fn replaying_error() -> Box<dyn std::error::Error + Send> {
+    RequestHandlerError::Message(
+        "A recorded stop is shown, step forward to return to the live program".to_string(),
+    )
+    .into()
+}
+
+fn replay_description(replay_index: Option<usize>, len: usize) -> Option<String> {
+    replay_index.map(|index| format!("Recorded stop {} of {} (read only)", index + 1, len))
+}
+
+/// Serve a snapshot as the current stop, the editor hears of it once the request was
+/// answered.
+fn show_snapshot(
+    dap: &DapSnapShot,
+    data: &mut crate::context::DebuggerData,
+    snapshot: StopSnapshot,
+    reason: StoppedEventReason,
+    description: Option<String>,
+) {
+    data.stacks = snapshot.stacks;
+    data.stopped_thread_id = snapshot.stopped_thread_id;
+    data.thread_stacks.clear();
+    data.current_frame_id = 0;
+    data.stop_column = None;
+
+    dap.send_after_response(Event::Stopped(StoppedEventBody {
+        reason,
+        thread_id: Some(to_target_thread_id(
+            dap.target_id,
+            snapshot.stopped_thread_id,
+        )),
+        description,
+        text: None,
+        all_threads_stopped: Some(dap.target_count() == 1),
+        preserve_focus_hint: None,
+        hit_breakpoint_ids: None,
+    }));
+}
+
+/// Record the live stop as the break reported it, nothing is fetched before the editor
+/// is told about the stop.
+pub async fn record_stop(dap: &DapSnapShot, by_step: bool) {
+    let mut data = dap.data.lock().await;
+    if !data.history.is_enabled() {
+        return;
+    }
+    let snapshot = StopSnapshot {
+        stacks: data.stacks.clone(),
+        stopped_thread_id: data.stopped_thread_id,
+        stop_seq: data.stop_seq,
+        by_step,
+    };
+    data.history.push(snapshot);
+}
+
+/// Fetch the tables among the top frame's locals up to the configured depth, so they can
+/// be browsed when the stop is replayed. Runs after the stop was reported and gives up
+/// once the debuggee runs again.
+pub async fn record_top_frame_tables(dap: &DapSnapShot) {
+    let (names, level, depth, stop_seq) = {
+        let data = dap.data.lock().await;
+        let Some(top) = data.stacks.first() else {
+            return;
+        };
+        if !data.history.is_enabled() {
+            return;
+        }
+        let names = top
+            .local_variables
+            .iter()
+            .chain(top.upvalue_variables.iter())
+            .filter(|variable| {
+                matches!(
+                    variable.value_type,
+                    ValueType::TTABLE | ValueType::TUSERDATA
+                )
+            })
+            .map(|variable| variable.name.clone())
+            .collect::<Vec<_>>();
+        (names, top.level as i64, data.history.depth, data.stop_seq)
+    };
+
+    for name in names {
+        let requests = dap.debugger_conn.lock().await.requests();
+        let result = requests
+            .eval_expr(name.clone(), 0, depth, level, &CancellationToken::new())
+            .await;
+        let children = match result {
+            Ok(eval_rsp) if eval_rsp.success => eval_rsp.value.and_then(|value| value.children),
+            Ok(_) => None,
+            Err(err) => {
+                log::error!("Failed to record {}: {}", name, err);
+                None
+            }
+        };
+
+        let mut data = dap.data.lock().await;
+        if !data.is_stopped() {
+            return;
+        }
+        let Some(snapshot) = data.history.live_mut(stop_seq) else {
+            return;
+        };
+        let variable = snapshot.stacks.first_mut().and_then(|top| {
+            top.local_variables
+                .iter_mut()
+                .chain(top.upvalue_variables.iter_mut())
+                .find(|variable| variable.name == name)
+        });
+        if let Some(variable) = variable {
+            variable.children = children;
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use dap::{
+    requests::StepInTargetsArguments,
+    responses::{GotoTargetsResponse, ResponseBody},
+    types::GotoTarget,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    context::DapSnapShot,
+    handler::{
+        RequestHandlerError,
+        stack_trace_request::{find_file_path, source_line},
+    },
+    lua_syntax::call_expressions,
+};
+
+use super::RequestResult;
+
+/// Offer the calls of the frame's current line, read from the resolved source file.
+/// The types crate has no stepInTargets response, the gotoTargets body has the same
+/// shape (`targets` with id, label, line and column) and is sent as `stepInTargets`.
+pub async fn on_step_in_targets_request(
+    dap: DapSnapShot,
+    step_in_targets_arguments: StepInTargetsArguments,
+    _: CancellationToken,
+) -> RequestResult {
+    log::info!(
+        "Received StepInTargets request: {:#?}",
+        step_in_targets_arguments
+    );
+    let Some((dap, Some(frame_id))) = dap.frame_target(Some(step_in_targets_arguments.frame_id))
+    else {
+        return Err(RequestHandlerError::Message(format!(
+            "Invalid frame id: {}",
+            step_in_targets_arguments.frame_id
+        ))
+        .into());
+    };
+
+    let client = *dap.client.lock().unwrap();
+    let mut data = dap.data.lock().await;
+    data.step_in_targets.clear();
+    let Some(stack) = data.get_stack(frame_id).cloned() else {
+        return Ok(ResponseBody::GotoTargets(GotoTargetsResponse {
+            targets: vec![],
+        }));
+    };
+
+    let line_text = match find_file_path(&mut data, stack.file.clone()).await? {
+        Some(path) => source_line(&mut data, &path, stack.line).unwrap_or_default(),
+        None => String::new(),
+    };
+
+    let mut targets = vec![];
+    for call in call_expressions(&line_text) {
+        data.step_in_targets.push(call.function_name().to_string());
+        // columns count chars
+        let column = line_text[..call.start].chars().count() as i64 + 1;
+        let end_column = line_text[..call.end].chars().count() as i64 + 1;
+        let line = client.client_line(stack.line as i64);
+        targets.push(GotoTarget {
+            id: data.step_in_targets.len() as i64,
+            label: call.callee,
+            line,
+            column: Some(client.client_column(column)),
+            end_line: Some(line),
+            end_column: Some(client.client_column(end_column)),
+            ..Default::default()
+        });
+    }
+
+    Ok(ResponseBody::GotoTargets(GotoTargetsResponse { targets }))
+}
@@ -1,16 +1,62 @@
 use dap::responses::{ResponseBody, ThreadsResponse};
 use tokio_util::sync::CancellationToken;
 
-use crate::context::DapSnapShot;
+use crate::{
+    context::{DapSnapShot, MAIN_THREAD_ID, to_target_thread_id},
+    handler::coroutine_threads::list_coroutines,
+};
 
 use super::RequestResult;
 
-pub async fn on_threads_request(_: DapSnapShot, _: (), _: CancellationToken) -> RequestResult {
+pub async fn on_threads_request(dap: DapSnapShot, _: (), _: CancellationToken) -> RequestResult {
     log::info!("Handling Threads Request");
-    Ok(ResponseBody::Threads(ThreadsResponse {
-        threads: vec![dap::types::Thread {
-            id: 1,
-            name: "Main Thread".to_string(),
-        }],
-    }))
+    let targets = dap.all_targets();
+    let show_target_name = targets.len() > 1;
+    let mut threads = vec![];
+    for target in targets {
+        let prefix = if show_target_name {
+            format!("[{}] ", target.target_name())
+        } else {
+            String::new()
+        };
+        for thread in target_threads(&target).await {
+            threads.push(dap::types::Thread {
+                id: to_target_thread_id(target.target_id, thread.id),
+                name: format!("{}{}", prefix, thread.name),
+            });
+        }
+    }
+
+    Ok(ResponseBody::Threads(ThreadsResponse { threads }))
+}
+
+async fn target_threads(dap: &DapSnapShot) -> Vec<dap::types::Thread> {
+    let (track_coroutines, is_stopped) = {
+        let data = dap.data.lock().await;
+        (
+            data.track_coroutines && data.coroutine_tracker_installed,
+            data.is_stopped(),
+        )
+    };
+
+    // the debuggee only answers evals while stopped, a running one lists the main thread
+    if track_coroutines && is_stopped {
+        let coroutines = list_coroutines(dap).await;
+        dap.data.lock().await.coroutines = coroutines;
+    }
+
+    let mut threads = vec![dap::types::Thread {
+        id: MAIN_THREAD_ID,
+        name: "Main Thread".to_string(),
+    }];
+    if track_coroutines {
+        let data = dap.data.lock().await;
+        for co in &data.coroutines {
+            threads.push(dap::types::Thread {
+                id: co.thread_id,
+                name: format!("Coroutine {} ({}) {}", co.tracker_id, co.status, co.source),
+            });
+        }
+    }
+    threads
 }
@@ -11,13 +11,21 @@ use super::RequestResult;
 pub async fn on_variable_request(
     dap: DapSnapShot,
     variable_argument: VariablesArguments,
-    _: CancellationToken,
+    cancel: CancellationToken,
 ) -> RequestResult {
+    let Some(dap) = dap.variables_target(variable_argument.variables_reference) else {
+        return Ok(ResponseBody::Variables(VariablesResponse {
+            variables: vec![],
+        }));
+    };
+    let requests = dap.debugger_conn.lock().await.requests();
     let mut data = dap.data.lock().await;
+    // a replayed stop is read only, its variables come from the recording
+    let requests = (!data.history.is_replaying()).then_some(requests);
     let cache_item = data.cache.get_cache(variable_argument.variables_reference);
     let cache = &mut data.cache;
     let variables = match cache_item {
-        Some(item) => item.compute_children(cache, dap.debugger_conn).await,
+        Some(item) => item.compute_children(cache, requests, &cancel).await,
         None => {
             vec![]
         }
@@ -13,12 +13,7 @@ const CRATE_NAME: &str = env!("CARGO_PKG_NAME");
 const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub fn init_logger(cmd_args: &CmdArgs) {
-    let level = match cmd_args.log_level {
-        LogLevel::Error => LevelFilter::Error,
-        LogLevel::Warn => LevelFilter::Warn,
-        LogLevel::Info => LevelFilter::Info,
-        LogLevel::Debug => LevelFilter::Debug,
-    };
+    let level = to_level_filter(cmd_args.log_level);
 
     let cmd_log_path = cmd_args.log_path.clone();
     if cmd_log_path.0.is_none() {
@@ -82,8 +77,8 @@ pub fn init_logger(cmd_args: &CmdArgs) {
                 message
             ))
         })
-        // set level
-        .level(level)
+        // keep every record in the dispatch, the runtime level is controlled by `set_log_level`
+        .level(LevelFilter::Debug)
         // set output
         .chain(log_file);
 
@@ -91,6 +86,7 @@ pub fn init_logger(cmd_args: &CmdArgs) {
         eprintln!("Failed to apply logger: {:?}", e);
         return;
     }
+    log::set_max_level(level);
 
     log::info!("{} v{}", CRATE_NAME, CRATE_VERSION);
 }
@@ -107,8 +103,8 @@ pub fn init_stderr_logger(level: LevelFilter) {
                 message
             ))
         })
-        // set level
-        .level(level)
+        // keep every record in the dispatch, the runtime level is controlled by `set_log_level`
+        .level(LevelFilter::Debug)
         // set output
         .chain(std::io::stderr());
 
@@ -116,6 +112,21 @@ pub fn init_stderr_logger(level: LevelFilter) {
         eprintln!("Failed to apply logger: {:?}", e);
         return;
     }
+    log::set_max_level(level);
 
     log::info!("{} v{}", CRATE_NAME, CRATE_VERSION);
 }
+
+/// Change the log level while the adapter is running.
+pub fn set_log_level(level: LogLevel) {
+    log::set_max_level(to_level_filter(level));
+}
+
+fn to_level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Error => LevelFilter::Error,
+        LogLevel::Warn => LevelFilter::Warn,
+        LogLevel::Info => LevelFilter::Info,
+        LogLevel::Debug => LevelFilter::Debug,
+    }
+}
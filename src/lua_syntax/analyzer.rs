@@ -0,0 +1,270 @@
+use super::lexer::{Token, TokenKind, tokenize};
+
+const STATEMENT_KEYWORDS: &[&str] = &[
+    "local", "if", "for", "while", "repeat", "return", "do", "goto", "break",
+];
+
+/// Returns true when evaluating the expression may change program state:
+/// any call or assignment counts, metamethods are not taken into account.
+pub fn has_side_effects(source: &str) -> bool {
+    let tokens = tokenize(source);
+    tokens.iter().enumerate().any(|(i, token)| {
+        token.is_symbol(source, "=") || (i > 0 && is_call_start(source, &tokens[i - 1], token))
+    })
+}
+
+/// Returns true when the source has to be executed as a chunk rather than
+/// evaluated as an expression.
+pub fn is_statement(source: &str) -> bool {
+    let tokens = tokenize(source);
+    let Some(first) = tokens.first() else {
+        return false;
+    };
+
+    if first.kind == TokenKind::Keyword {
+        let text = first.text(source);
+        if STATEMENT_KEYWORDS.contains(&text) {
+            return true;
+        }
+        // `function foo() end` declares, `function() end` is a value
+        if text == "function" && tokens.get(1).is_some_and(|t| t.kind == TokenKind::Name) {
+            return true;
+        }
+    }
+    if first.is_symbol(source, "::") {
+        return true;
+    }
+
+    let mut depth = 0;
+    for token in &tokens {
+        if token.kind != TokenKind::Symbol {
+            continue;
+        }
+        match token.text(source) {
+            "(" | "{" | "[" => depth += 1,
+            ")" | "}" | "]" => depth -= 1,
+            "=" | ";" if depth == 0 => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// `prev` followed by `token` starts a call: `f(`, `f"s"`, `f{`, `t:m(`
+fn is_call_start(source: &str, prev: &Token, token: &Token) -> bool {
+    let prev_ends_prefix_exp = match prev.kind {
+        TokenKind::Name => true,
+        TokenKind::Symbol => matches!(prev.text(source), ")" | "]"),
+        _ => false,
+    };
+    if !prev_ends_prefix_exp {
+        return false;
+    }
+
+    token.kind == TokenKind::String || token.is_symbol(source, "(") || token.is_symbol(source, "{")
+}
+
+/// Plain names assigned by a chunk, e.g. `a` and `b` in `a, b = 1, 2`.
+/// Field assignments are skipped, so are names the chunk declares with `local` or `for`
+/// anywhere, their assignments may hit the chunk's own variable.
+pub fn assigned_names(source: &str) -> Vec<String> {
+    let tokens = tokenize(source);
+    let mut names: Vec<String> = vec![];
+    let mut brace_depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        if token.is_symbol(source, "{") {
+            brace_depth += 1;
+        } else if token.is_symbol(source, "}") {
+            brace_depth -= 1;
+        }
+        // `{ a = 1 }` is a table field, not an assignment
+        if brace_depth > 0 || !token.is_symbol(source, "=") {
+            continue;
+        }
+
+        let mut targets = vec![];
+        let mut j = i;
+        let is_local = loop {
+            if j == 0 || tokens[j - 1].kind != TokenKind::Name {
+                break false;
+            }
+            let name = &tokens[j - 1];
+            let prev = if j >= 2 { Some(&tokens[j - 2]) } else { None };
+            match prev {
+                Some(prev) if prev.is_symbol(source, ".") || prev.is_symbol(source, ":") => {
+                    break false;
+                }
+                Some(prev)
+                    if prev.is_keyword(source, "local") || prev.is_keyword(source, "for") =>
+                {
+                    break true;
+                }
+                Some(prev) if prev.is_symbol(source, ",") => {
+                    targets.push(name.text(source).to_string());
+                    j -= 2;
+                }
+                _ => {
+                    targets.push(name.text(source).to_string());
+                    break false;
+                }
+            }
+        };
+
+        if !is_local {
+            for name in targets.into_iter().rev() {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+
+    let declared = declared_names(source, &tokens);
+    names.retain(|name| !declared.contains(name));
+    names
+}
+
+/// Names declared by `local`, `local function` and `for` in the chunk.
+fn declared_names(source: &str, tokens: &[Token]) -> Vec<String> {
+    let mut declared = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        if !token.is_keyword(source, "local") && !token.is_keyword(source, "for") {
+            continue;
+        }
+        let mut j = i + 1;
+        if tokens
+            .get(j)
+            .is_some_and(|next| next.is_keyword(source, "function"))
+        {
+            j += 1;
+        }
+        while let Some(name) = tokens.get(j).filter(|name| name.kind == TokenKind::Name) {
+            declared.push(name.text(source).to_string());
+            j += 1;
+            // attributes, `local x <const> = 1`
+            if tokens
+                .get(j)
+                .is_some_and(|next| next.is_symbol(source, "<"))
+            {
+                j += 3;
+            }
+            if !tokens
+                .get(j)
+                .is_some_and(|next| next.is_symbol(source, ","))
+            {
+                break;
+            }
+            j += 1;
+        }
+    }
+    declared
+}
+
+pub fn contains_keyword(source: &str, keyword: &str) -> bool {
+    tokenize(source)
+        .iter()
+        .any(|token| token.is_keyword(source, keyword))
+}
+
+/// An expression list or one ending with a call or `...` can produce several values.
+pub fn may_return_multiple(source: &str) -> bool {
+    let tokens = tokenize(source);
+    let Some(last) = tokens.last() else {
+        return false;
+    };
+    if last.is_symbol(source, "...") || last.is_symbol(source, ")") {
+        return true;
+    }
+
+    let mut depth = 0;
+    for token in &tokens {
+        if token.kind != TokenKind::Symbol {
+            continue;
+        }
+        match token.text(source) {
+            "(" | "{" | "[" => depth += 1,
+            ")" | "}" | "]" => depth -= 1,
+            "," if depth == 0 => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_effects() {
+        for source in ["a", "a.b[c]", "a == b", "#t + 1", "'f(x)'", "-- f()\na"] {
+            assert!(!has_side_effects(source), "{}", source);
+        }
+        for source in [
+            "f()", "t:m()", "f'x'", "f{1}", "a.b(c)", "(f)()", "t[1]()", "a = 1",
+        ] {
+            assert!(has_side_effects(source), "{}", source);
+        }
+    }
+
+    #[test]
+    fn statements() {
+        for source in [
+            "a = 1",
+            "a, b = b, a",
+            "t[i] = nil",
+            "local x = 1",
+            "if a then end",
+            "for i = 1, 3 do end",
+            "return a",
+            "function f() end",
+            "::label::",
+            "f(); g()",
+        ] {
+            assert!(is_statement(source), "{}", source);
+        }
+        for source in ["", "a", "a == b", "f(a = 1)", "{ a = 1 }", "function() end"] {
+            assert!(!is_statement(source), "{}", source);
+        }
+    }
+
+    #[test]
+    fn assigned_names_skip_locals_and_fields() {
+        assert_eq!(assigned_names("a, b = b, a"), vec!["a", "b"]);
+        assert_eq!(assigned_names("a = 1 b = a a = 2"), vec!["a", "b"]);
+        assert!(assigned_names("local a, b = 1, 2").is_empty());
+        assert!(assigned_names("for i = 1, 3 do end").is_empty());
+        assert!(assigned_names("t.a = 1 t:m() s[1] = 2").is_empty());
+        assert!(assigned_names("f { a = 1 }").is_empty());
+        assert!(assigned_names("a == b").is_empty());
+    }
+
+    #[test]
+    fn assigned_names_skip_redeclared_names() {
+        assert!(assigned_names("local x = 5; x = x + 1").is_empty());
+        assert!(assigned_names("local x; x = 1").is_empty());
+        assert!(assigned_names("local a, b <const> = 1, 2 b = 3").is_empty());
+        assert!(assigned_names("local function f() end f = nil").is_empty());
+        assert!(assigned_names("for k, v in pairs(t) do v = 1 end").is_empty());
+        assert_eq!(assigned_names("local y = 1 x = y"), vec!["x"]);
+    }
+
+    #[test]
+    fn keywords_outside_strings() {
+        assert!(contains_keyword("if a then return end", "return"));
+        assert!(!contains_keyword("print('return')", "return"));
+        assert!(!contains_keyword("returned = 1", "return"));
+    }
+
+    #[test]
+    fn multiple_results() {
+        for source in ["a, b", "f()", "t:m(1)", "..."] {
+            assert!(may_return_multiple(source), "{}", source);
+        }
+        for source in ["", "a", "f(a, b).x", "{1, 2}", "(f())[1]"] {
+            assert!(!may_return_multiple(source), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use super::lexer::{Token, TokenKind, tokenize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallExpr {
+    /// callee as written, `player:move` in `player:move(1)`
+    pub callee: String,
+    /// byte offset where the callee starts
+    pub start: usize,
+    /// byte offset after the arguments
+    pub end: usize,
+}
+
+impl CallExpr {
+    /// name the called function reports in its stack frame
+    pub fn function_name(&self) -> &str {
+        self.callee
+            .rsplit(['.', ':'])
+            .next()
+            .unwrap_or(&self.callee)
+    }
+}
+
+/// Calls of named functions in `source`, in the order they run: arguments are
+/// evaluated first, so `a(b(c()))` gives `c`, `b`, `a`.
+pub fn call_expressions(source: &str) -> Vec<CallExpr> {
+    let tokens = tokenize(source);
+    let mut calls = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        let opens_args = token.is_symbol(source, "(")
+            || token.is_symbol(source, "{")
+            || token.kind == TokenKind::String;
+        if !opens_args || i == 0 || tokens[i - 1].kind != TokenKind::Name {
+            continue;
+        }
+
+        let mut first = i - 1;
+        while first >= 2
+            && (tokens[first - 1].is_symbol(source, ".")
+                || tokens[first - 1].is_symbol(source, ":"))
+            && tokens[first - 2].kind == TokenKind::Name
+        {
+            first -= 2;
+        }
+        // `function name(` declares, it does not call
+        if first > 0 && tokens[first - 1].is_keyword(source, "function") {
+            continue;
+        }
+
+        let end = if token.kind == TokenKind::String {
+            token.end
+        } else if token.is_symbol(source, "(") {
+            closing_end(source, &tokens[i..], "(", ")")
+        } else {
+            closing_end(source, &tokens[i..], "{", "}")
+        };
+        calls.push(CallExpr {
+            callee: source[tokens[first].start..tokens[i - 1].end].to_string(),
+            start: tokens[first].start,
+            end,
+        });
+    }
+
+    calls.sort_by_key(|call| call.end);
+    calls
+}
+
+/// End of the bracket opened by the first token, the end of the source if it is not closed.
+fn closing_end(source: &str, tokens: &[Token], open: &str, close: &str) -> usize {
+    let mut depth = 0;
+    for token in tokens {
+        if token.is_symbol(source, open) {
+            depth += 1;
+        } else if token.is_symbol(source, close) {
+            depth -= 1;
+            if depth == 0 {
+                return token.end;
+            }
+        }
+    }
+    source.len()
+}
@@ -0,0 +1,99 @@
+use super::{
+    analyzer::has_side_effects,
+    lexer::{TokenKind, tokenize},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionPrefix {
+    /// expression left of the last `.` or `:`, `player.bag` in `player.bag:ad`
+    pub receiver: Option<String>,
+    /// the receiver is followed by `:`, only methods make sense
+    pub is_method: bool,
+    /// name typed so far
+    pub partial: String,
+    /// byte offset where the partial name starts
+    pub partial_start: usize,
+}
+
+/// Work out what is being completed at the end of `source`.
+/// Receivers which would need a call to be resolved are dropped.
+pub fn parse_completion_prefix(source: &str) -> CompletionPrefix {
+    let tokens = tokenize(source);
+    let mut end = tokens.len();
+    let (partial, partial_start) = match tokens.last() {
+        Some(last)
+            if matches!(last.kind, TokenKind::Name | TokenKind::Keyword)
+                && last.end == source.len() =>
+        {
+            end -= 1;
+            (last.text(source).to_string(), last.start)
+        }
+        _ => (String::new(), source.len()),
+    };
+
+    let mut prefix = CompletionPrefix {
+        receiver: None,
+        is_method: false,
+        partial,
+        partial_start,
+    };
+
+    if end == 0 {
+        return prefix;
+    }
+    let separator = &tokens[end - 1];
+    if !separator.is_symbol(source, ".") && !separator.is_symbol(source, ":") {
+        return prefix;
+    }
+    prefix.is_method = separator.is_symbol(source, ":");
+
+    let mut i = end - 1;
+    let mut start = None;
+    let mut needs_base = true;
+    while i > 0 {
+        let prev = &tokens[i - 1];
+        if prev.kind == TokenKind::Name {
+            start = Some(prev.start);
+            needs_base = false;
+            i -= 1;
+            if i > 0 && tokens[i - 1].is_symbol(source, ".") {
+                i -= 1;
+                needs_base = true;
+                continue;
+            }
+            break;
+        } else if prev.is_symbol(source, "]") {
+            let mut depth = 0;
+            let mut j = i - 1;
+            loop {
+                if tokens[j].is_symbol(source, "]") {
+                    depth += 1;
+                } else if tokens[j].is_symbol(source, "[") {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                if j == 0 {
+                    return prefix;
+                }
+                j -= 1;
+            }
+            i = j;
+            needs_base = true;
+        } else {
+            break;
+        }
+    }
+
+    if let Some(start) = start
+        && !needs_base
+    {
+        let receiver = source[start..separator.start].trim();
+        if !has_side_effects(receiver) {
+            prefix.receiver = Some(receiver.to_string());
+        }
+    }
+
+    prefix
+}
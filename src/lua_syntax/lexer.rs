@@ -0,0 +1,262 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Name,
+    Keyword,
+    Number,
+    String,
+    Symbol,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// byte offset of the first char
+    pub start: usize,
+    /// byte offset after the last char
+    pub end: usize,
+}
+
+impl Token {
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+
+    pub fn is_symbol(&self, source: &str, symbol: &str) -> bool {
+        self.kind == TokenKind::Symbol && self.text(source) == symbol
+    }
+
+    pub fn is_keyword(&self, source: &str, keyword: &str) -> bool {
+        self.kind == TokenKind::Keyword && self.text(source) == keyword
+    }
+}
+
+pub const LUA_KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+const SYMBOLS: &[&str] = &[
+    "...", "..", "==", "~=", "<=", ">=", "<<", ">>", "//", "::", "+", "-", "*", "/", "%", "^", "#",
+    "&", "~", "|", "<", ">", "=", "(", ")", "{", "}", "[", "]", ";", ":", ",", ".",
+];
+
+/// Split lua source into tokens, comments and whitespace are dropped.
+/// The lexer never fails, unterminated strings run to the end of the source.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let bytes = source.as_bytes();
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+
+        let start = pos;
+        let kind = if c == b'-' && bytes.get(pos + 1) == Some(&b'-') {
+            pos += 2;
+            if let Some(level) = long_bracket_level(bytes, pos) {
+                pos = skip_long_bracket(bytes, pos, level);
+            } else {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+            }
+            continue;
+        } else if c.is_ascii_alphabetic() || c == b'_' || c >= 0x80 {
+            while pos < bytes.len()
+                && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_' || bytes[pos] >= 0x80)
+            {
+                pos += 1;
+            }
+            if LUA_KEYWORDS.contains(&&source[start..pos]) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Name
+            }
+        } else if c.is_ascii_digit()
+            || (c == b'.' && bytes.get(pos + 1).is_some_and(|b| b.is_ascii_digit()))
+        {
+            pos = skip_number(bytes, pos);
+            TokenKind::Number
+        } else if c == b'"' || c == b'\'' {
+            pos = skip_short_string(bytes, pos);
+            TokenKind::String
+        } else if let Some(level) = long_bracket_level(bytes, pos) {
+            pos = skip_long_bracket(bytes, pos, level);
+            TokenKind::String
+        } else if let Some(symbol) = SYMBOLS
+            .iter()
+            .find(|symbol| source[pos..].starts_with(**symbol))
+        {
+            pos += symbol.len();
+            TokenKind::Symbol
+        } else {
+            pos += 1;
+            while pos < bytes.len() && !source.is_char_boundary(pos) {
+                pos += 1;
+            }
+            TokenKind::Unknown
+        };
+
+        tokens.push(Token {
+            kind,
+            start,
+            end: pos,
+        });
+    }
+
+    tokens
+}
+
+/// `[[`, `[=[`, `[==[` ... returns the number of `=`
+fn long_bracket_level(bytes: &[u8], pos: usize) -> Option<usize> {
+    if bytes.get(pos) != Some(&b'[') {
+        return None;
+    }
+    let mut level = 0;
+    while bytes.get(pos + 1 + level) == Some(&b'=') {
+        level += 1;
+    }
+    if bytes.get(pos + 1 + level) == Some(&b'[') {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+fn skip_long_bracket(bytes: &[u8], pos: usize, level: usize) -> usize {
+    let mut pos = pos + level + 2;
+    while pos < bytes.len() {
+        if bytes[pos] == b']' {
+            let mut end = pos + 1;
+            let mut count = 0;
+            while bytes.get(end) == Some(&b'=') {
+                end += 1;
+                count += 1;
+            }
+            if count == level && bytes.get(end) == Some(&b']') {
+                return end + 1;
+            }
+        }
+        pos += 1;
+    }
+    bytes.len()
+}
+
+fn skip_short_string(bytes: &[u8], pos: usize) -> usize {
+    let quote = bytes[pos];
+    let mut pos = pos + 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'\n' => return pos,
+            c if c == quote => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+    bytes.len()
+}
+
+fn skip_number(bytes: &[u8], pos: usize) -> usize {
+    let is_hex = bytes[pos] == b'0' && matches!(bytes.get(pos + 1), Some(b'x') | Some(b'X'));
+    let mut pos = if is_hex { pos + 2 } else { pos };
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        let is_exponent = if is_hex {
+            c == b'p' || c == b'P'
+        } else {
+            c == b'e' || c == b'E'
+        };
+        if is_exponent && matches!(bytes.get(pos + 1), Some(b'+') | Some(b'-')) {
+            pos += 2;
+        } else if c.is_ascii_alphanumeric() || c == b'.' {
+            pos += 1;
+        } else {
+            break;
+        }
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds_and_texts(source: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(source)
+            .into_iter()
+            .map(|token| (token.kind, token.text(source)))
+            .collect()
+    }
+
+    #[test]
+    fn splits_names_keywords_and_symbols() {
+        assert_eq!(
+            kinds_and_texts("local a_1 = t.x ~= nil"),
+            vec![
+                (TokenKind::Keyword, "local"),
+                (TokenKind::Name, "a_1"),
+                (TokenKind::Symbol, "="),
+                (TokenKind::Name, "t"),
+                (TokenKind::Symbol, "."),
+                (TokenKind::Name, "x"),
+                (TokenKind::Symbol, "~="),
+                (TokenKind::Keyword, "nil"),
+            ]
+        );
+        // the longest symbol wins
+        assert_eq!(
+            kinds_and_texts("a...b..c"),
+            vec![
+                (TokenKind::Name, "a"),
+                (TokenKind::Symbol, "..."),
+                (TokenKind::Name, "b"),
+                (TokenKind::Symbol, ".."),
+                (TokenKind::Name, "c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_numbers() {
+        for number in ["1", "3.14", ".5", "1e-3", "0x1F", "0x1p+4"] {
+            assert_eq!(kinds_and_texts(number), vec![(TokenKind::Number, number)]);
+        }
+    }
+
+    #[test]
+    fn reads_strings() {
+        assert_eq!(
+            kinds_and_texts(r#""a\"b" 'c'"#),
+            vec![(TokenKind::String, r#""a\"b""#), (TokenKind::String, "'c'")]
+        );
+        assert_eq!(
+            kinds_and_texts("[==[ ]] ]==] x"),
+            vec![(TokenKind::String, "[==[ ]] ]==]"), (TokenKind::Name, "x")]
+        );
+        // unterminated strings run to the end
+        assert_eq!(kinds_and_texts("'abc"), vec![(TokenKind::String, "'abc")]);
+        assert_eq!(kinds_and_texts("[[abc"), vec![(TokenKind::String, "[[abc")]);
+    }
+
+    #[test]
+    fn drops_comments() {
+        assert_eq!(
+            kinds_and_texts("a -- f()\nb --[[ g() ]] c"),
+            vec![
+                (TokenKind::Name, "a"),
+                (TokenKind::Name, "b"),
+                (TokenKind::Name, "c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_multibyte_chars_whole() {
+        assert_eq!(kinds_and_texts("变量"), vec![(TokenKind::Name, "变量")]);
+        assert_eq!(kinds_and_texts("$"), vec![(TokenKind::Unknown, "$")]);
+    }
+}
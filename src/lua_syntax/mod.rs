@@ -0,0 +1,11 @@
+mod analyzer;
+mod calls;
+mod completion;
+mod lexer;
+
+pub use analyzer::{
+    assigned_names, contains_keyword, has_side_effects, is_statement, may_return_multiple,
+};
+pub use calls::call_expressions;
+pub use completion::parse_completion_prefix;
+pub use lexer::LUA_KEYWORDS;
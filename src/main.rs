@@ -2,11 +2,13 @@ mod cmd_args;
 mod context;
 mod handler;
 mod logger;
+mod lua_syntax;
+mod relay;
 
 use std::io::{BufReader, BufWriter, Stdin, Stdout};
 
 use clap::Parser;
-use cmd_args::CmdArgs;
+use cmd_args::{CmdArgs, Command};
 use context::EmmyLuaDebugContext;
 use dap::server::Server;
 use handler::on_request_dispatch;
@@ -17,6 +19,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cmd_args = CmdArgs::parse();
 
     init_logger(&cmd_args);
+    if let Some(Command::Relay(relay_args)) = cmd_args.command {
+        return relay::run(relay_args)
+            .await
+            .map_err(|e| e as Box<dyn std::error::Error>);
+    }
     let current_path = std::env::current_dir()?;
     log::info!("Starting path {:?}", current_path);
 
@@ -0,0 +1,79 @@
+use std::error::Error;
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    cmd_args::{RelayArgs, TlsSide},
+    context::{
+        ACCEPT_RETRY_DELAY, CONNECT_ATTEMPT_TIMEOUT, DebuggerEndpoint, DebuggerListener,
+        LinkReader, LinkWriter, TlsOptions, answer_challenge, open_link, tls_acceptor,
+        tls_connector,
+    },
+};
+
+/// Run the relay until it is killed. Every accepted connection gets its own link to the
+/// other side, the bytes are passed on unchanged.
+pub async fn run(args: RelayArgs) -> Result<(), Box<dyn Error + Send>> {
+    let options = TlsOptions {
+        cert: args.cert,
+        key: args.key,
+        ca: args.ca,
+        insecure_skip_verify: args.insecure_skip_verify,
+    };
+    let (acceptor, connector) = match args.tls_side {
+        TlsSide::Listen => (Some(tls_acceptor(&options)?), None),
+        TlsSide::Connect => (None, Some(tls_connector(&options)?)),
+    };
+
+    let listener =
+        DebuggerListener::bind(&DebuggerEndpoint::Tcp(args.listen.clone()), acceptor).await?;
+    log::info!("Relaying {} to {}", args.listen, args.connect);
+    let target = DebuggerEndpoint::Tcp(args.connect);
+    loop {
+        // peers failing their handshake are dropped inside accept, a failing listener
+        // gets a moment before the next try
+        let (reader, writer, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::error!("Failed to accept relay connection: {}", e);
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        log::info!("Relay connection from {}", peer);
+        let target = target.clone();
+        let connector = connector.clone();
+        let secret = args.secret.clone();
+        tokio::spawn(async move {
+            let opened = open_link(&target, Some(CONNECT_ATTEMPT_TIMEOUT), connector.as_ref());
+            let (mut target_reader, mut target_writer) = match opened.await {
+                Ok(link) => link,
+                Err(e) => {
+                    log::error!("Failed to relay {} to {}: {}", peer, target, e);
+                    return;
+                }
+            };
+            // a listening adapter configured with a secret challenges every link
+            if let Some(secret) = &secret
+                && let Err(e) =
+                    answer_challenge(&mut target_reader, &mut target_writer, secret).await
+            {
+                log::error!("Failed to authenticate to {}: {}", target, e);
+                return;
+            }
+            tokio::join!(
+                forward(reader, target_writer),
+                forward(target_reader, writer)
+            );
+            log::info!("Relay connection from {} closed", peer);
+        });
+    }
+}
+
+/// Copy until either end closes, then close the writer so the other direction ends too.
+async fn forward(mut reader: LinkReader, mut writer: LinkWriter) {
+    if let Err(e) = tokio::io::copy(&mut reader, &mut writer).await {
+        log::info!("Relay stream ended: {}", e);
+    }
+    let _ = writer.shutdown().await;
+}